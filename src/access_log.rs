@@ -0,0 +1,351 @@
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use regex::Regex;
+use serde::Serialize;
+use std::{
+    fmt::Write as _,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Sender, SyncSender},
+    thread,
+    time::Instant,
+};
+
+use crate::{
+    body::CountingBody,
+    config::{AccessLogFormat, Config, LogRotation},
+    error::{AppError, Result},
+    handlers::AppState,
+};
+
+/// One access-log record, written once the response body has been sent.
+#[derive(Debug, Clone, Serialize)]
+pub struct AccessLogEntry {
+    pub timestamp: DateTime<Utc>,
+    pub request_id: String,
+    pub client_ip: Option<String>,
+    pub method: String,
+    pub path: String,
+    pub protocol: String,
+    pub status: u16,
+    pub bytes: u64,
+    pub latency_ms: f64,
+    pub referer: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl AccessLogEntry {
+    /// Renders the entry as a single line, so line-based log shippers see one record per line.
+    pub fn format(&self, format: AccessLogFormat) -> String {
+        match format {
+            AccessLogFormat::Json => serde_json::to_string(self).unwrap_or_default(),
+            AccessLogFormat::Human => format!(
+                "{} {} {} {} {} {}B {:.1}ms {}",
+                self.timestamp.to_rfc3339_opts(SecondsFormat::Millis, true),
+                self.client_ip.as_deref().unwrap_or("-"),
+                self.method,
+                self.path,
+                self.status,
+                self.bytes,
+                self.latency_ms,
+                self.request_id,
+            ),
+            AccessLogFormat::Common => self.common(),
+            AccessLogFormat::Combined => format!(
+                "{} \"{}\" \"{}\"",
+                self.common(),
+                escape_quoted(self.referer.as_deref().unwrap_or("-")),
+                escape_quoted(self.user_agent.as_deref().unwrap_or("-")),
+            ),
+            AccessLogFormat::Logfmt => self.logfmt(),
+        }
+    }
+
+    /// `host ident authuser [date] "request" status bytes`
+    fn common(&self) -> String {
+        format!(
+            "{} - - [{}] \"{} {} {}\" {} {}",
+            self.client_ip.as_deref().unwrap_or("-"),
+            self.timestamp.format("%d/%b/%Y:%H:%M:%S %z"),
+            self.method,
+            escape_quoted(&self.path),
+            self.protocol,
+            self.status,
+            if self.bytes == 0 { "-".to_string() } else { self.bytes.to_string() },
+        )
+    }
+
+    fn logfmt(&self) -> String {
+        let fields = [
+            ("time", Some(self.timestamp.to_rfc3339_opts(SecondsFormat::Millis, true))),
+            ("request_id", Some(self.request_id.clone())),
+            ("client_ip", self.client_ip.clone()),
+            ("method", Some(self.method.clone())),
+            ("path", Some(self.path.clone())),
+            ("protocol", Some(self.protocol.clone())),
+            ("status", Some(self.status.to_string())),
+            ("bytes", Some(self.bytes.to_string())),
+            ("latency_ms", Some(format!("{:.3}", self.latency_ms))),
+            ("referer", self.referer.clone()),
+            ("user_agent", self.user_agent.clone()),
+        ];
+
+        let mut line = String::new();
+        for (key, value) in fields {
+            let Some(value) = value else { continue };
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            if value.is_empty() || value.contains([' ', '"', '=', '\\']) {
+                let _ = write!(line, "{}=\"{}\"", key, escape_quoted(&value));
+            } else {
+                let _ = write!(line, "{}={}", key, value);
+            }
+        }
+        line
+    }
+}
+
+/// Escapes backslashes, quotes and control characters for a double-quoted field.
+fn escape_quoted(value: &str) -> String {
+    value.escape_default().to_string()
+}
+
+/// Writes one line per request, skipping paths that match `--log-ignore-path`. Lines are
+/// handed to a dedicated writer thread, so slow disks and pipes never block the runtime.
+pub struct AccessLog {
+    format: AccessLogFormat,
+    ignore: Option<Regex>,
+    writer: Sender<WriterMessage>,
+}
+
+enum WriterMessage {
+    Line(String),
+    /// Acknowledged once every line sent before it has been written
+    Flush(SyncSender<()>),
+}
+
+impl AccessLog {
+    /// Access log writing to `--access-log-file`, or to stdout when no file is configured.
+    pub fn new(config: &Config) -> Result<Self> {
+        let sink: Box<dyn Write + Send> = match &config.access_log_file {
+            Some(path) => Box::new(
+                RotatingFile::open(
+                    path,
+                    config.access_log_max_size,
+                    config.access_log_rotation,
+                    config.access_log_max_files,
+                )
+                .map_err(|e| {
+                    AppError::Logging(format!("cannot open access log {}: {}", path.display(), e))
+                })?,
+            ),
+            None => Box::new(io::stdout()),
+        };
+
+        Self::with_writer(config, sink)
+    }
+
+    pub fn with_writer(config: &Config, mut sink: Box<dyn Write + Send>) -> Result<Self> {
+        let ignore = config
+            .log_ignore_path
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .map_err(|e| AppError::Logging(format!("invalid log ignore pattern: {}", e)))?;
+
+        let (writer, messages) = mpsc::channel();
+        thread::Builder::new()
+            .name("access-log".to_string())
+            .spawn(move || {
+                for message in messages {
+                    match message {
+                        WriterMessage::Line(line) => {
+                            let _ = sink.write_all(line.as_bytes()).and_then(|_| sink.flush());
+                        },
+                        WriterMessage::Flush(done) => {
+                            let _ = done.send(());
+                        },
+                    }
+                }
+            })
+            .map_err(|e| AppError::Logging(format!("cannot start access log writer: {}", e)))?;
+
+        Ok(AccessLog {
+            format: config
+                .access_log_format
+                .unwrap_or_else(|| AccessLogFormat::from(&config.log_format)),
+            ignore,
+            writer,
+        })
+    }
+
+    pub fn is_ignored(&self, path: &str) -> bool {
+        self.ignore.as_ref().is_some_and(|ignore| ignore.is_match(path))
+    }
+
+    pub fn write(&self, entry: &AccessLogEntry) {
+        // A single write per entry keeps rotation from splitting a line across files
+        let line = entry.format(self.format) + "\n";
+        let _ = self.writer.send(WriterMessage::Line(line));
+    }
+
+    /// Blocks until every entry logged so far has been written.
+    pub fn flush(&self) {
+        let (done, flushed) = mpsc::sync_channel(1);
+        if self.writer.send(WriterMessage::Flush(done)).is_ok() {
+            let _ = flushed.recv();
+        }
+    }
+}
+
+/// Log file that is rotated by size and/or on an hourly or daily schedule. Rotated files are
+/// renamed `<file>.1` (newest) to `<file>.<max_files>` (oldest); older ones are deleted.
+#[derive(Debug)]
+pub struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: Option<u64>,
+    rotation: LogRotation,
+    period: String,
+    max_files: usize,
+}
+
+impl RotatingFile {
+    pub fn open(
+        path: &Path,
+        max_size: Option<u64>,
+        rotation: LogRotation,
+        max_files: usize,
+    ) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let size = file.metadata()?.len();
+
+        Ok(RotatingFile {
+            path: path.to_path_buf(),
+            file,
+            size,
+            max_size,
+            rotation,
+            period: Self::current_period(rotation),
+            max_files,
+        })
+    }
+
+    fn current_period(rotation: LogRotation) -> String {
+        match rotation {
+            LogRotation::Never => String::new(),
+            LogRotation::Hourly => Utc::now().format("%Y%m%d%H").to_string(),
+            LogRotation::Daily => Utc::now().format("%Y%m%d").to_string(),
+        }
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        path.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let _ = fs::remove_file(self.rotated_path(self.max_files));
+            for index in (1..self.max_files).rev() {
+                let from = self.rotated_path(index);
+                if from.exists() {
+                    fs::rename(from, self.rotated_path(index + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+        }
+
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let period = Self::current_period(self.rotation);
+        let size_exceeded =
+            self.max_size.is_some_and(|max| self.size > 0 && self.size + buf.len() as u64 > max);
+
+        if period != self.period || size_exceeded {
+            self.period = period;
+            self.rotate()?;
+        }
+
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Middleware that assigns each request an ID (reusing `x-request-id` when the client sent
+/// one), echoes it in the response and logs the request once its body has been sent.
+pub async fn log_requests(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let access_log = state.access_log.clone();
+    if access_log.is_ignored(request.uri().path()) {
+        return next.run(request).await;
+    }
+
+    let timestamp = Utc::now();
+    let start = Instant::now();
+    let headers = request.headers();
+    let request_id =
+        header(headers, "x-request-id").unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let referer = header(headers, "referer");
+    let user_agent = header(headers, "user-agent");
+    let client_ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_string());
+    let method = request.method().to_string();
+    let path = request.uri().path_and_query().map(|p| p.to_string()).unwrap_or_default();
+    let protocol = format!("{:?}", request.version());
+
+    let mut response = next.run(request).await;
+    let status = response.status().as_u16();
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().entry("x-request-id").or_insert(value);
+    }
+
+    response.map(|body| {
+        CountingBody::wrap(body, move |bytes| {
+            access_log.write(&AccessLogEntry {
+                timestamp,
+                request_id,
+                client_ip,
+                method,
+                path,
+                protocol,
+                status,
+                bytes,
+                latency_ms: start.elapsed().as_secs_f64() * 1000.0,
+                referer,
+                user_agent,
+            });
+        })
+    })
+}
+
+fn header(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers.get(name).and_then(|v| v.to_str().ok()).map(|v| v.to_string())
+}
@@ -0,0 +1,570 @@
+use axum::{
+    Json,
+    body::Bytes,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use chrono::{DateTime, Utc};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use std::{
+    collections::{HashMap, VecDeque},
+    path::Path,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+use tokio::time::sleep;
+use tracing::info;
+
+use crate::{
+    error::{AppError, Result},
+    handlers::AppState,
+    rules::OneOrMany,
+};
+
+const API_VERSION: &str = "admission.k8s.io/v1";
+
+#[derive(Debug, Deserialize)]
+struct AdmissionRulesFile {
+    #[serde(default)]
+    rules: Vec<AdmissionRuleSpec>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AdmissionRuleSpec {
+    name: Option<String>,
+    #[serde(rename = "match", default)]
+    matcher: AdmissionMatchSpec,
+    #[serde(default = "default_allowed")]
+    allowed: bool,
+    code: Option<u16>,
+    message: Option<String>,
+    patch: Option<Value>,
+    #[serde(default)]
+    warnings: Vec<String>,
+    delay_ms: Option<u64>,
+}
+
+fn default_allowed() -> bool {
+    true
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct AdmissionMatchSpec {
+    operation: Option<OneOrMany>,
+    kind: Option<OneOrMany>,
+    resource: Option<OneOrMany>,
+    namespace: Option<OneOrMany>,
+    name: Option<OneOrMany>,
+    user: Option<OneOrMany>,
+    #[serde(default)]
+    fields: HashMap<String, String>,
+}
+
+/// The parts of an `AdmissionReview` request that rules can match on.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdmissionRequest {
+    #[serde(default)]
+    pub uid: String,
+    #[serde(default)]
+    pub kind: GroupVersionKind,
+    #[serde(default)]
+    pub resource: GroupVersionResource,
+    pub name: Option<String>,
+    pub namespace: Option<String>,
+    #[serde(default)]
+    pub operation: String,
+    #[serde(default)]
+    pub user_info: UserInfo,
+    #[serde(default)]
+    pub object: Value,
+    #[serde(default)]
+    pub old_object: Value,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct GroupVersionKind {
+    #[serde(default)]
+    pub group: String,
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub kind: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct GroupVersionResource {
+    #[serde(default)]
+    pub group: String,
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub resource: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct UserInfo {
+    #[serde(default)]
+    pub username: String,
+}
+
+impl AdmissionRequest {
+    /// The object being admitted, or the object being removed for `DELETE`.
+    fn subject(&self) -> &Value {
+        if self.object.is_null() { &self.old_object } else { &self.object }
+    }
+}
+
+/// The `response` of an `AdmissionReview`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdmissionResponse {
+    pub uid: String,
+    pub allowed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<AdmissionStatus>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub patch_type: Option<&'static str>,
+    /// Base64-encoded JSONPatch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub patch: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdmissionStatus {
+    pub code: u16,
+    pub message: String,
+}
+
+/// An admission review answered by `/__admission`.
+#[derive(Debug, Serialize)]
+pub struct AdmissionRecord {
+    pub id: u64,
+    pub timestamp: DateTime<Utc>,
+    pub uid: String,
+    pub operation: String,
+    pub kind: String,
+    pub namespace: Option<String>,
+    pub name: Option<String>,
+    pub dry_run: bool,
+    pub allowed: bool,
+    /// Name of the rule that decided the review
+    pub rule: Option<String>,
+    pub patched: bool,
+    pub request: Value,
+    #[serde(skip)]
+    size: usize,
+}
+
+/// A single admission rule: request matchers plus the decision to answer with.
+#[derive(Debug)]
+pub struct AdmissionRule {
+    pub name: Option<String>,
+    operations: Vec<String>,
+    kinds: Vec<String>,
+    resources: Vec<String>,
+    namespaces: Vec<String>,
+    names: Vec<String>,
+    users: Vec<String>,
+    fields: Vec<(String, Regex)>,
+    allowed: bool,
+    code: Option<u16>,
+    message: Option<String>,
+    patch: Option<String>,
+    warnings: Vec<String>,
+    delay: Option<Duration>,
+}
+
+/// Validating and mutating webhook backed by `--admission-rules-file`. The first matching
+/// rule decides; reviews matching no rule are allowed unchanged. The most recent reviews are
+/// kept for `GET /__admission`, bounded by count and by their total serialized size.
+#[derive(Debug)]
+pub struct AdmissionController {
+    rules: Vec<AdmissionRule>,
+    reviews: Mutex<Reviews>,
+    capacity: usize,
+    max_bytes: usize,
+    next_id: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct Reviews {
+    items: VecDeque<Arc<AdmissionRecord>>,
+    bytes: usize,
+}
+
+impl AdmissionController {
+    pub fn new(rules: Vec<AdmissionRule>, capacity: usize, max_bytes: usize) -> Self {
+        AdmissionController {
+            rules,
+            reviews: Mutex::new(Reviews::default()),
+            capacity,
+            max_bytes,
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    /// Loads rules from a YAML or TOML file, chosen by extension like `--rules-file`.
+    pub fn load(path: Option<&Path>, capacity: usize, max_bytes: usize) -> Result<Self> {
+        let Some(path) = path else {
+            return Ok(Self::new(Vec::new(), capacity, max_bytes));
+        };
+
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            AppError::Admission(format!("failed to read {}: {}", path.display(), e))
+        })?;
+        let is_toml = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("toml"));
+
+        let file: AdmissionRulesFile = if is_toml {
+            toml::from_str(&contents).map_err(|e| AppError::Admission(e.to_string()))?
+        } else {
+            serde_yaml::from_str(&contents).map_err(|e| AppError::Admission(e.to_string()))?
+        };
+
+        let rules = file
+            .rules
+            .into_iter()
+            .enumerate()
+            .map(|(index, spec)| {
+                AdmissionRule::compile(spec)
+                    .map_err(|e| AppError::Admission(format!("rule #{}: {}", index + 1, e)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self::new(rules, capacity, max_bytes))
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Decides a review and records it, returning the response and how long to wait before
+    /// sending it. Secret values are redacted from the recorded request.
+    pub fn review(
+        &self,
+        request: &AdmissionRequest,
+        mut raw: Value,
+    ) -> (AdmissionResponse, Option<Duration>) {
+        let rule = self.rules.iter().find(|rule| rule.matches(request));
+
+        let response = match rule {
+            Some(rule) => rule.respond(&request.uid),
+            None => AdmissionResponse {
+                uid: request.uid.clone(),
+                allowed: true,
+                status: None,
+                patch_type: None,
+                patch: None,
+                warnings: Vec::new(),
+            },
+        };
+
+        info!(
+            "Admission {} {} {}/{}: {}",
+            request.operation,
+            request.kind.kind,
+            request.namespace.as_deref().unwrap_or("-"),
+            request.name.as_deref().unwrap_or("-"),
+            if response.allowed { "allowed" } else { "denied" }
+        );
+
+        if request.kind.group.is_empty() && request.kind.kind == "Secret" {
+            redact_secret(&mut raw);
+        }
+
+        let size = raw.to_string().len();
+        self.record(AdmissionRecord {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            timestamp: Utc::now(),
+            uid: request.uid.clone(),
+            operation: request.operation.clone(),
+            kind: request.kind.kind.clone(),
+            namespace: request.namespace.clone(),
+            name: request.name.clone(),
+            dry_run: request.dry_run,
+            allowed: response.allowed,
+            rule: rule.and_then(|rule| rule.name.clone()),
+            patched: response.patch.is_some(),
+            request: raw,
+            size,
+        });
+
+        (response, rule.and_then(|rule| rule.delay))
+    }
+
+    /// Keeps a review, evicting the oldest ones to stay within bounds. Reviews larger than the
+    /// whole byte budget are not kept.
+    fn record(&self, record: AdmissionRecord) {
+        if self.capacity == 0 || record.size > self.max_bytes {
+            return;
+        }
+
+        let mut reviews = self.lock();
+        reviews.bytes += record.size;
+        reviews.items.push_back(Arc::new(record));
+
+        while reviews.items.len() > self.capacity || reviews.bytes > self.max_bytes {
+            match reviews.items.pop_front() {
+                Some(evicted) => reviews.bytes -= evicted.size,
+                None => break,
+            }
+        }
+    }
+
+    /// Recorded reviews, oldest first.
+    pub fn reviews(&self) -> Vec<Arc<AdmissionRecord>> {
+        self.lock().items.iter().cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        let mut reviews = self.lock();
+        reviews.items.clear();
+        reviews.bytes = 0;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Reviews> {
+        self.reviews.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Annotation in which `kubectl apply` keeps a full copy of the applied object.
+const LAST_APPLIED: &str = "kubectl.kubernetes.io/last-applied-configuration";
+
+/// Replaces the values of a Secret's `data` and `stringData`, and drops its last-applied
+/// copy, in both the object and the old object of a review request.
+fn redact_secret(request: &mut Value) {
+    for key in ["object", "oldObject"] {
+        let Some(object) = request.get_mut(key).and_then(Value::as_object_mut) else {
+            continue;
+        };
+
+        for field in ["data", "stringData"] {
+            if let Some(values) = object.get_mut(field).and_then(Value::as_object_mut) {
+                values.values_mut().for_each(|value| *value = json!("REDACTED"));
+            }
+        }
+
+        if let Some(annotations) = object
+            .get_mut("metadata")
+            .and_then(|metadata| metadata.get_mut("annotations"))
+            .and_then(Value::as_object_mut)
+        {
+            annotations.remove(LAST_APPLIED);
+        }
+    }
+}
+
+impl AdmissionRule {
+    fn compile(spec: AdmissionRuleSpec) -> Result<Self> {
+        let matcher = spec.matcher;
+        let list = |values: Option<OneOrMany>| values.map(OneOrMany::into_vec).unwrap_or_default();
+
+        let fields = matcher
+            .fields
+            .into_iter()
+            .map(|(pointer, pattern)| {
+                if !pointer.is_empty() && !pointer.starts_with('/') {
+                    return Err(AppError::Admission(format!(
+                        "field {} is not a JSON pointer",
+                        pointer
+                    )));
+                }
+                Regex::new(&pattern)
+                    .map(|re| (pointer, re))
+                    .map_err(|e| AppError::Admission(e.to_string()))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        if let Some(code) = spec.code {
+            StatusCode::from_u16(code)
+                .map_err(|_| AppError::Admission(format!("invalid code: {}", code)))?;
+        }
+
+        let patch = match spec.patch {
+            Some(patch) => Some(encode_patch(&patch)?),
+            None => None,
+        };
+
+        Ok(AdmissionRule {
+            name: spec.name,
+            operations: list(matcher.operation),
+            kinds: list(matcher.kind),
+            resources: list(matcher.resource),
+            namespaces: list(matcher.namespace),
+            names: list(matcher.name),
+            users: list(matcher.user),
+            fields,
+            allowed: spec.allowed,
+            code: spec.code,
+            message: spec.message,
+            patch,
+            warnings: spec.warnings,
+            delay: spec.delay_ms.map(Duration::from_millis),
+        })
+    }
+
+    fn matches(&self, request: &AdmissionRequest) -> bool {
+        let any_of = |values: &[String], actual: &str, ignore_case: bool| {
+            values.is_empty()
+                || values.iter().any(|value| {
+                    if ignore_case { value.eq_ignore_ascii_case(actual) } else { value == actual }
+                })
+        };
+
+        if !any_of(&self.operations, &request.operation, true)
+            || !any_of(&self.kinds, &request.kind.kind, true)
+            || !any_of(&self.resources, &request.resource.resource, true)
+            || !any_of(&self.namespaces, request.namespace.as_deref().unwrap_or_default(), false)
+            || !any_of(&self.names, request.name.as_deref().unwrap_or_default(), false)
+            || !any_of(&self.users, &request.user_info.username, false)
+        {
+            return false;
+        }
+
+        let subject = request.subject();
+        self.fields.iter().all(|(pointer, re)| match subject.pointer(pointer) {
+            Some(Value::String(value)) => re.is_match(value),
+            Some(value) => re.is_match(&value.to_string()),
+            None => false,
+        })
+    }
+
+    fn respond(&self, uid: &str) -> AdmissionResponse {
+        let status = if self.allowed {
+            self.message.as_ref().map(|message| AdmissionStatus {
+                code: self.code.unwrap_or(200),
+                message: message.clone(),
+            })
+        } else {
+            Some(AdmissionStatus {
+                code: self.code.unwrap_or(403),
+                message: self.message.clone().unwrap_or_else(|| match &self.name {
+                    Some(name) => format!("denied by k8swalski rule {}", name),
+                    None => "denied by k8swalski".to_string(),
+                }),
+            })
+        };
+
+        // The API server ignores patches on denied requests
+        let patch = self.patch.clone().filter(|_| self.allowed);
+
+        AdmissionResponse {
+            uid: uid.to_string(),
+            allowed: self.allowed,
+            status,
+            patch_type: patch.as_ref().map(|_| "JSONPatch"),
+            patch,
+            warnings: self.warnings.clone(),
+        }
+    }
+}
+
+/// Checks that `patch` is a list of JSONPatch operations and base64-encodes it.
+fn encode_patch(patch: &Value) -> Result<String> {
+    let operations = patch
+        .as_array()
+        .ok_or_else(|| AppError::Admission("patch must be a list of operations".to_string()))?;
+
+    for operation in operations {
+        let op = operation.get("op").and_then(Value::as_str);
+        let path = operation.get("path").and_then(Value::as_str);
+        match (op, path) {
+            (Some("add" | "remove" | "replace" | "move" | "copy" | "test"), Some(_)) => {},
+            _ => {
+                return Err(AppError::Admission(format!("invalid patch operation: {}", operation)));
+            },
+        }
+    }
+
+    let json = serde_json::to_vec(patch).map_err(|e| AppError::Admission(e.to_string()))?;
+    Ok(STANDARD.encode(json))
+}
+
+fn review_response(api_version: &str, response: AdmissionResponse) -> Response {
+    Json(json!({
+        "apiVersion": api_version,
+        "kind": "AdmissionReview",
+        "response": response,
+    }))
+    .into_response()
+}
+
+/// Answers reviews that cannot be decided with a denial, so the API server always gets a
+/// well-formed `AdmissionReview` back.
+fn malformed(api_version: &str, uid: String, message: String) -> Response {
+    review_response(
+        api_version,
+        AdmissionResponse {
+            uid,
+            allowed: false,
+            status: Some(AdmissionStatus { code: 400, message }),
+            patch_type: None,
+            patch: None,
+            warnings: Vec::new(),
+        },
+    )
+}
+
+/// Handles an `AdmissionReview` posted by the API server.
+pub async fn review(State(state): State<AppState>, body: Bytes) -> Response {
+    let review: Value = match serde_json::from_slice(&body) {
+        Ok(review) => review,
+        Err(e) => {
+            return malformed(
+                API_VERSION,
+                String::new(),
+                format!("invalid AdmissionReview: {}", e),
+            );
+        },
+    };
+
+    // Answer v1beta1 reviews in kind
+    let api_version = review
+        .get("apiVersion")
+        .and_then(Value::as_str)
+        .filter(|version| version.starts_with("admission.k8s.io/"))
+        .unwrap_or(API_VERSION);
+
+    let Some(raw) = review.get("request").cloned() else {
+        return malformed(api_version, String::new(), "AdmissionReview has no request".to_string());
+    };
+
+    let request: AdmissionRequest = match serde_json::from_value(raw.clone()) {
+        Ok(request) => request,
+        Err(e) => {
+            let uid = raw.get("uid").and_then(Value::as_str).unwrap_or_default().to_string();
+            return malformed(api_version, uid, format!("invalid AdmissionReview request: {}", e));
+        },
+    };
+
+    let (response, delay) = state.admission.review(&request, raw);
+    if let Some(delay) = delay {
+        sleep(delay).await;
+    }
+
+    review_response(api_version, response)
+}
+
+pub async fn list_reviews(State(state): State<AppState>) -> Json<Vec<Arc<AdmissionRecord>>> {
+    Json(state.admission.reviews())
+}
+
+pub async fn clear_reviews(State(state): State<AppState>) -> StatusCode {
+    state.admission.clear();
+    StatusCode::NO_CONTENT
+}
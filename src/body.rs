@@ -0,0 +1,125 @@
+use axum::{
+    body::{Body, BodyDataStream, Bytes},
+    http::{HeaderMap, header},
+};
+use futures_util::{StreamExt, stream};
+use http_body::{Body as HttpBody, Frame, SizeHint};
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::sleep;
+
+/// Response body wrapper that reports how many bytes were actually sent once the body is
+/// dropped, so streamed and aborted bodies are measured too. Size hints are passed through,
+/// so `content-length` framing is unaffected.
+pub struct CountingBody<F: FnOnce(u64)> {
+    inner: Body,
+    bytes: u64,
+    on_end: Option<F>,
+}
+
+impl<F: FnOnce(u64) + Send + Unpin + 'static> CountingBody<F> {
+    pub fn wrap(body: Body, on_end: F) -> Body {
+        Body::new(CountingBody { inner: body, bytes: 0, on_end: Some(on_end) })
+    }
+}
+
+impl<F: FnOnce(u64) + Unpin> HttpBody for CountingBody<F> {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let poll = Pin::new(&mut self.inner).poll_frame(cx);
+        if let Poll::Ready(Some(Ok(frame))) = &poll {
+            if let Some(data) = frame.data_ref() {
+                self.bytes += data.len() as u64;
+            }
+        }
+        poll
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl<F: FnOnce(u64)> Drop for CountingBody<F> {
+    fn drop(&mut self) {
+        if let Some(on_end) = self.on_end.take() {
+            on_end(self.bytes);
+        }
+    }
+}
+
+/// Exact size of a response body: its size hint, else the `content-length` set by handlers
+/// that stream generated bodies.
+pub fn exact_len(headers: &HeaderMap, body: &Body) -> Option<u64> {
+    body.size_hint()
+        .exact()
+        .or_else(|| headers.get(header::CONTENT_LENGTH)?.to_str().ok()?.parse().ok())
+}
+
+struct Cutoff {
+    inner: BodyDataStream,
+    remaining: Option<u64>,
+    error: Option<&'static str>,
+    done: bool,
+}
+
+/// Forwards `body` frame by frame until `limit` bytes have been sent, or until the first
+/// non-empty frame when no limit is given. It then pauses so the server can flush what was
+/// sent and ends the body, failing it with `error` if one is given. Nothing is buffered
+/// beyond the current frame.
+pub fn cut_off(body: Body, limit: Option<u64>, error: Option<&'static str>) -> Body {
+    let state = Cutoff { inner: body.into_data_stream(), remaining: limit, error, done: false };
+
+    Body::from_stream(
+        stream::unfold(state, |mut state| async move {
+            loop {
+                if state.done {
+                    return None;
+                }
+
+                let frame = match state.remaining {
+                    Some(0) => None,
+                    _ => state.inner.next().await,
+                };
+
+                match frame {
+                    Some(Ok(bytes)) if bytes.is_empty() => continue,
+                    Some(Ok(mut bytes)) => {
+                        match &mut state.remaining {
+                            Some(remaining) => {
+                                bytes.truncate(usize::try_from(*remaining).unwrap_or(usize::MAX));
+                                *remaining -= bytes.len() as u64;
+                            },
+                            None => state.remaining = Some(0),
+                        }
+                        return Some((Ok(bytes), state));
+                    },
+                    Some(Err(e)) => {
+                        state.done = true;
+                        return Some((Err(e), state));
+                    },
+                    None => {
+                        state.done = true;
+                        sleep(Duration::from_millis(10)).await;
+                        let error = state.error?;
+                        return Some((Err(axum::Error::new(io::Error::other(error))), state));
+                    },
+                }
+            }
+        })
+        .fuse(),
+    )
+}
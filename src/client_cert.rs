@@ -0,0 +1,248 @@
+use axum::http::HeaderMap;
+use chrono::{DateTime, Utc};
+use rustls::pki_types::CertificateDer;
+use serde::Serialize;
+use std::net::IpAddr;
+use x509_parser::{extensions::GeneralName, prelude::*};
+
+use crate::tls::TlsInfo;
+
+/// Client certificate of the request, from the TLS handshake or from proxy headers.
+#[derive(Debug, Default, Serialize)]
+pub struct ClientCertInfo {
+    /// `tls` when presented to the HTTPS listener, `xfcc` when taken from Envoy's
+    /// `x-forwarded-client-cert`, `header` when forwarded by another proxy
+    pub source: &'static str,
+    pub subject: String,
+    pub issuer: String,
+    /// SANs in OpenSSL notation, e.g. `DNS:example.com, URI:spiffe://cluster.local/ns/default`
+    pub subjectaltname: Option<String>,
+    pub info: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub serial: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub not_before: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub not_after: Option<DateTime<Utc>>,
+    /// SHA-256 of the DER-encoded leaf certificate, colon-separated hex
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fingerprint_sha256: Option<String>,
+    /// Number of certificates the client sent, leaf included
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chain_depth: Option<usize>,
+    /// Workload identity from a `spiffe://` URI SAN
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spiffe: Option<SpiffeId>,
+    /// Elements of `x-forwarded-client-cert`, oldest hop first
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub forwarded: Vec<XfccElement>,
+}
+
+/// A SPIFFE ID such as `spiffe://cluster.local/ns/default/sa/frontend`. Namespace and service
+/// account are filled for IDs following the Kubernetes `/ns/<ns>/sa/<sa>` convention.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SpiffeId {
+    pub id: String,
+    pub trust_domain: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service_account: Option<String>,
+}
+
+impl SpiffeId {
+    pub fn parse(uri: &str) -> Option<Self> {
+        let rest = uri.strip_prefix("spiffe://")?;
+        let (trust_domain, path) = rest.split_once('/').unwrap_or((rest, ""));
+        if trust_domain.is_empty() {
+            return None;
+        }
+
+        let segments: Vec<&str> = path.split('/').collect();
+        let segment = |key: &str| {
+            segments
+                .windows(2)
+                .find(|pair| pair[0] == key && !pair[1].is_empty())
+                .map(|pair| pair[1].to_string())
+        };
+
+        Some(SpiffeId {
+            id: uri.to_string(),
+            trust_domain: trust_domain.to_string(),
+            namespace: segment("ns"),
+            service_account: segment("sa"),
+        })
+    }
+}
+
+/// One element of an `x-forwarded-client-cert` header, describing the client certificate a
+/// proxy saw on one hop.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct XfccElement {
+    /// SAN of the proxy's own certificate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub by: Option<String>,
+    /// SHA-256 of the client certificate, hex
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uri: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dns: Vec<String>,
+}
+
+/// Parses an `x-forwarded-client-cert` value: comma-separated elements of `;`-separated
+/// `Key=value` pairs, where values may be double-quoted. `Cert` and `Chain` are skipped.
+pub fn parse_xfcc(value: &str) -> Vec<XfccElement> {
+    let mut elements = Vec::new();
+    let mut element = XfccElement::default();
+    let mut pair = String::new();
+    let mut quoted = false;
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if quoted => pair.extend(chars.next()),
+            '"' => quoted = !quoted,
+            ';' | ',' if !quoted => {
+                add_xfcc_pair(&mut element, &pair);
+                pair.clear();
+                if c == ',' {
+                    elements.push(std::mem::take(&mut element));
+                }
+            },
+            _ => pair.push(c),
+        }
+    }
+    add_xfcc_pair(&mut element, &pair);
+    elements.push(element);
+
+    elements.retain(|element| *element != XfccElement::default());
+    elements
+}
+
+fn add_xfcc_pair(element: &mut XfccElement, pair: &str) {
+    let Some((key, value)) = pair.split_once('=') else { return };
+    let value = value.trim().to_string();
+    if value.is_empty() {
+        return;
+    }
+
+    match key.trim().to_ascii_lowercase().as_str() {
+        "by" => element.by = Some(value),
+        "hash" => element.hash = Some(value),
+        "subject" => element.subject = Some(value),
+        "uri" => element.uri = Some(value),
+        "dns" => element.dns.push(value),
+        _ => {},
+    }
+}
+
+/// Prefers the certificate verified by the HTTPS listener over `x-forwarded-client-cert`, and
+/// that over the headers set by other reverse proxies (nginx, etc.).
+pub fn extract_client_cert(headers: &HeaderMap, tls: Option<&TlsInfo>) -> Option<ClientCertInfo> {
+    tls.and_then(|tls| from_chain(&tls.peer_certificates))
+        .or_else(|| from_xfcc(headers))
+        .or_else(|| from_headers(headers))
+}
+
+fn from_chain(chain: &[CertificateDer<'_>]) -> Option<ClientCertInfo> {
+    let leaf = chain.first()?;
+    let (_, cert) = X509Certificate::from_der(leaf).ok()?;
+    let validity = cert.validity();
+
+    let general_names = cert
+        .subject_alternative_name()
+        .ok()
+        .flatten()
+        .map(|san| san.value.general_names.as_slice())
+        .unwrap_or_default();
+    let names: Vec<String> = general_names.iter().filter_map(format_general_name).collect();
+    let spiffe = general_names.iter().find_map(|name| match name {
+        GeneralName::URI(uri) => SpiffeId::parse(uri),
+        _ => None,
+    });
+
+    Some(ClientCertInfo {
+        source: "tls",
+        subject: cert.subject().to_string(),
+        issuer: cert.issuer().to_string(),
+        subjectaltname: (!names.is_empty()).then(|| names.join(", ")),
+        serial: Some(cert.raw_serial_as_string()),
+        not_before: DateTime::from_timestamp(validity.not_before.timestamp(), 0),
+        not_after: DateTime::from_timestamp(validity.not_after.timestamp(), 0),
+        fingerprint_sha256: Some(fingerprint(leaf)),
+        chain_depth: Some(chain.len()),
+        spiffe,
+        ..Default::default()
+    })
+}
+
+fn format_general_name(name: &GeneralName<'_>) -> Option<String> {
+    match name {
+        GeneralName::DNSName(dns) => Some(format!("DNS:{}", dns)),
+        GeneralName::URI(uri) => Some(format!("URI:{}", uri)),
+        GeneralName::RFC822Name(email) => Some(format!("email:{}", email)),
+        GeneralName::IPAddress(bytes) => {
+            let ip = match bytes.len() {
+                4 => IpAddr::from(<[u8; 4]>::try_from(*bytes).ok()?),
+                16 => IpAddr::from(<[u8; 16]>::try_from(*bytes).ok()?),
+                _ => return None,
+            };
+            Some(format!("IP Address:{}", ip))
+        },
+        _ => None,
+    }
+}
+
+fn fingerprint(der: &[u8]) -> String {
+    ring::digest::digest(&ring::digest::SHA256, der)
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{:02X}", byte))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// The client of the most recent hop is the last element of the header.
+fn from_xfcc(headers: &HeaderMap) -> Option<ClientCertInfo> {
+    let forwarded = parse_xfcc(headers.get("x-forwarded-client-cert")?.to_str().ok()?);
+    let client = forwarded.last()?;
+
+    let names: Vec<String> = client
+        .dns
+        .iter()
+        .map(|dns| format!("DNS:{}", dns))
+        .chain(client.uri.iter().map(|uri| format!("URI:{}", uri)))
+        .collect();
+
+    Some(ClientCertInfo {
+        source: "xfcc",
+        subject: client.subject.clone().unwrap_or_default(),
+        subjectaltname: (!names.is_empty()).then(|| names.join(", ")),
+        spiffe: client.uri.as_deref().and_then(SpiffeId::parse),
+        forwarded,
+        ..Default::default()
+    })
+}
+
+fn from_headers(headers: &HeaderMap) -> Option<ClientCertInfo> {
+    let header =
+        |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(|s| s.to_string());
+
+    let subject = header("x-client-cert-subject").or_else(|| header("ssl-client-subject-dn"))?;
+    let issuer = header("x-client-cert-issuer")
+        .or_else(|| header("ssl-client-issuer-dn"))
+        .unwrap_or_default();
+
+    Some(ClientCertInfo {
+        source: "header",
+        subject,
+        issuer,
+        subjectaltname: header("x-client-cert-san"),
+        info: header("x-client-cert-info"),
+        ..Default::default()
+    })
+}
@@ -1,7 +1,9 @@
-use clap::Parser;
+use clap::{Args, Parser, Subcommand};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
+use crate::payload::parse_size;
+
 #[derive(Parser, Debug, Clone)]
 #[command(name = "k8swalski")]
 #[command(author, version, about, long_about = None)]
@@ -22,6 +24,22 @@ pub struct Config {
     #[arg(long, env = "TLS_KEY_PATH", default_value = "/tmp/key.pem")]
     pub tls_key_path: PathBuf,
 
+    /// Extra DNS names or IPs for the generated self-signed certificate, comma-separated
+    /// (e.g. k8swalski.default.svc)
+    #[arg(long, env = "TLS_SAN", value_delimiter = ',')]
+    pub tls_san: Vec<String>,
+
+    /// CA bundle used to verify client certificates on the HTTPS listener
+    #[cfg(feature = "mtls")]
+    #[arg(long, env = "TLS_CLIENT_CA")]
+    pub tls_client_ca: Option<PathBuf>,
+
+    /// Client certificate verification: none, optional or required [default: required with
+    /// --tls-client-ca, otherwise none]
+    #[cfg(feature = "mtls")]
+    #[arg(long, env = "TLS_CLIENT_AUTH")]
+    pub tls_client_auth: Option<ClientAuth>,
+
     /// Maximum request body size in bytes
     #[arg(long, env = "MAX_BODY_SIZE", default_value = "10485760")]
     pub max_body_size: usize,
@@ -38,15 +56,104 @@ pub struct Config {
     #[arg(long, env = "LOG_IGNORE_PATH")]
     pub log_ignore_path: Option<String>,
 
+    /// Access log format: "human", "json", "common", "combined" or "logfmt" [default: --log-format]
+    #[arg(long, env = "ACCESS_LOG_FORMAT")]
+    pub access_log_format: Option<AccessLogFormat>,
+
+    /// Write access logs to this file instead of stdout
+    #[arg(long, env = "ACCESS_LOG_FILE")]
+    pub access_log_file: Option<PathBuf>,
+
+    /// Rotate the access log file once it reaches this size (e.g. 100MB)
+    #[arg(long, env = "ACCESS_LOG_MAX_SIZE", value_parser = parse_size)]
+    pub access_log_max_size: Option<u64>,
+
+    /// Rotate the access log file on a schedule: "never", "hourly" or "daily"
+    #[arg(long, env = "ACCESS_LOG_ROTATION", default_value = "never")]
+    pub access_log_rotation: LogRotation,
+
+    /// Number of rotated access log files to keep
+    #[arg(long, env = "ACCESS_LOG_MAX_FILES", default_value = "5")]
+    pub access_log_max_files: usize,
+
     /// Include environment variables in response
     #[arg(long, env = "INCLUDE_ENV_VARS")]
     pub include_env_vars: bool,
 
+    /// Include container resource limits and usage in response
+    #[arg(long, env = "INCLUDE_RESOURCES")]
+    pub include_resources: bool,
+
+    /// Downward API volume with pod labels, annotations and identity files
+    #[arg(long, env = "PODINFO_DIR", default_value = "/etc/podinfo")]
+    pub podinfo_dir: PathBuf,
+
     /// Decode JWT tokens in Authorization header
     #[cfg(feature = "jwt")]
     #[arg(long, env = "JWT_HEADER")]
     pub jwt_header: Option<String>,
 
+    /// HMAC secret used to verify JWT signatures
+    #[cfg(feature = "jwt")]
+    #[arg(long, env = "JWT_SECRET")]
+    pub jwt_secret: Option<String>,
+
+    /// PEM public key (RSA, EC or Ed25519) used to verify JWT signatures
+    #[cfg(feature = "jwt")]
+    #[arg(long, env = "JWT_PUBLIC_KEY")]
+    pub jwt_public_key: Option<PathBuf>,
+
+    /// Local JWKS file used to verify JWT signatures
+    #[cfg(feature = "jwt")]
+    #[arg(long, env = "JWT_JWKS_FILE")]
+    pub jwt_jwks_file: Option<PathBuf>,
+
+    /// Required JWT issuer (iss)
+    #[cfg(feature = "jwt")]
+    #[arg(long, env = "JWT_ISSUER")]
+    pub jwt_issuer: Option<String>,
+
+    /// Accepted JWT audiences (aud), comma-separated
+    #[cfg(feature = "jwt")]
+    #[arg(long, env = "JWT_AUDIENCE", value_delimiter = ',')]
+    pub jwt_audience: Vec<String>,
+
+    /// Clock skew allowed when checking exp and nbf, in seconds
+    #[cfg(feature = "jwt")]
+    #[arg(long, env = "JWT_LEEWAY_SECS", default_value = "60")]
+    pub jwt_leeway_secs: u64,
+
+    /// Answer requests with a JWT that fails verification with 401
+    #[cfg(feature = "jwt")]
+    #[arg(long, env = "JWT_REJECT_INVALID")]
+    pub jwt_reject_invalid: bool,
+
+    /// Serve a mock OIDC issuer with this issuer URL (e.g. http://localhost:8080)
+    #[cfg(feature = "jwt")]
+    #[arg(long, env = "OIDC_ISSUER")]
+    pub oidc_issuer: Option<String>,
+
+    /// PEM private key (RSA, EC P-256 or Ed25519) the mock issuer signs with [default:
+    /// generated P-256 key]
+    #[cfg(feature = "jwt")]
+    #[arg(long, env = "OIDC_SIGNING_KEY")]
+    pub oidc_signing_key: Option<PathBuf>,
+
+    /// Lifetime of tokens minted by the mock issuer, in seconds
+    #[cfg(feature = "jwt")]
+    #[arg(long, env = "OIDC_TOKEN_TTL_SECS", default_value = "3600")]
+    pub oidc_token_ttl_secs: u64,
+
+    /// Audience of minted tokens [default: the client ID]
+    #[cfg(feature = "jwt")]
+    #[arg(long, env = "OIDC_AUDIENCE")]
+    pub oidc_audience: Option<String>,
+
+    /// Extra claims added to every minted token, as a JSON object
+    #[cfg(feature = "jwt")]
+    #[arg(long, env = "OIDC_CLAIMS")]
+    pub oidc_claims: Option<String>,
+
     /// Enable Prometheus metrics endpoint
     #[cfg(feature = "prometheus")]
     #[arg(long, env = "PROMETHEUS")]
@@ -76,11 +183,43 @@ pub struct Config {
     #[arg(long, env = "MAX_HEADER_SIZE", default_value = "16384")]
     pub max_header_size: usize,
 
+    /// Maximum number of request headers
+    #[arg(long, env = "MAX_HEADERS", default_value = "100")]
+    pub max_headers: usize,
+
+    /// Close HTTP/1 connections after each response
+    #[arg(long, env = "DISABLE_KEEP_ALIVE")]
+    pub disable_keep_alive: bool,
+
+    /// Seconds allowed for a client to send the request headers (HTTP/1)
+    #[arg(long, env = "HEADER_READ_TIMEOUT_SECS", default_value = "30")]
+    pub header_read_timeout_secs: u64,
+
+    /// Maximum concurrent streams per HTTP/2 connection
+    #[arg(long, env = "HTTP2_MAX_CONCURRENT_STREAMS", default_value = "200")]
+    pub http2_max_concurrent_streams: u32,
+
+    /// HTTP/2 initial stream window size in bytes
+    #[arg(long, env = "HTTP2_STREAM_WINDOW_SIZE")]
+    pub http2_stream_window_size: Option<u32>,
+
+    /// HTTP/2 initial connection window size in bytes
+    #[arg(long, env = "HTTP2_CONNECTION_WINDOW_SIZE")]
+    pub http2_connection_window_size: Option<u32>,
+
+    /// Interval in seconds between HTTP/2 keepalive pings (disabled by default)
+    #[arg(long, env = "HTTP2_KEEP_ALIVE_INTERVAL_SECS")]
+    pub http2_keep_alive_interval_secs: Option<u64>,
+
+    /// Seconds to wait for an HTTP/2 keepalive ping acknowledgement before closing
+    #[arg(long, env = "HTTP2_KEEP_ALIVE_TIMEOUT_SECS", default_value = "20")]
+    pub http2_keep_alive_timeout_secs: u64,
+
     /// Disable echoing response back to client (send empty response)
     #[arg(long, env = "ECHO_BACK_TO_CLIENT")]
     pub echo_back_to_client: Option<bool>,
 
-    /// Log JSON output without newlines
+    /// Log human-format diagnostics on single lines instead of pretty-printing them
     #[arg(long, env = "LOG_WITHOUT_NEWLINE")]
     pub log_without_newline: bool,
 
@@ -88,9 +227,132 @@ pub struct Config {
     #[arg(long, env = "OVERRIDE_RESPONSE_BODY_FILE_PATH")]
     pub override_response_body_file_path: Option<PathBuf>,
 
+    /// Render the override response body file as a template against the echoed request
+    #[arg(long, env = "OVERRIDE_RESPONSE_BODY_TEMPLATE")]
+    pub override_response_body_template: bool,
+
+    /// Mock rules file (YAML or TOML) matched before falling back to the echo response
+    #[arg(long, env = "RULES_FILE")]
+    pub rules_file: Option<PathBuf>,
+
+    /// Fraction of requests (0.0-1.0) answered with the injected error code
+    #[arg(long, env = "ERROR_RATE")]
+    pub error_rate: Option<f64>,
+
+    /// Status code returned for injected errors
+    #[arg(long, env = "ERROR_CODE", default_value = "503")]
+    pub error_code: u16,
+
+    /// Fraction of requests (0.0-1.0) whose connection is dropped mid-response
+    #[arg(long, env = "ABORT_RATE")]
+    pub abort_rate: Option<f64>,
+
+    /// Seed for fault injection randomness, for reproducible runs
+    #[arg(long, env = "FAULT_SEED")]
+    pub fault_seed: Option<u64>,
+
+    /// Number of recent requests kept for the /__history endpoints (0 disables them).
+    /// Recorded requests include credentials such as Authorization and Cookie headers
+    #[arg(long, env = "HISTORY_SIZE", default_value = "0")]
+    pub history_size: usize,
+
+    /// Maximum total size in bytes of the recorded request history
+    #[arg(long, env = "HISTORY_MAX_BYTES", default_value = "10485760")]
+    pub history_max_bytes: usize,
+
+    /// Enable the /__stream live tail of handled requests, including their credentials
+    #[arg(long, env = "ENABLE_STREAM")]
+    pub enable_stream: bool,
+
+    /// Enable the /__load CPU and memory load generator endpoints
+    #[arg(long, env = "ENABLE_LOAD")]
+    pub enable_load: bool,
+
+    /// Most CPU cores a load job may burn [default: available CPUs]
+    #[arg(long, env = "LOAD_MAX_CORES")]
+    pub load_max_cores: Option<f64>,
+
+    /// Most memory a load job may allocate (e.g. 2GB) [default: unlimited]
+    #[arg(long, env = "LOAD_MAX_MEMORY", value_parser = parse_size)]
+    pub load_max_memory: Option<u64>,
+
+    /// Longest a load job may run, in seconds
+    #[arg(long, env = "LOAD_MAX_DURATION_SECS", default_value = "3600")]
+    pub load_max_duration_secs: u64,
+
+    /// Enable the /__admission webhook endpoint, allowing every review unless rules are given
+    #[arg(long, env = "ENABLE_ADMISSION")]
+    pub enable_admission: bool,
+
+    /// Admission webhook rules file (YAML or TOML) used by /__admission; implies
+    /// --enable-admission
+    #[arg(long, env = "ADMISSION_RULES_FILE")]
+    pub admission_rules_file: Option<PathBuf>,
+
+    /// Number of recent admission reviews kept for GET /__admission (0 disables recording)
+    #[arg(long, env = "ADMISSION_HISTORY_SIZE", default_value = "100")]
+    pub admission_history_size: usize,
+
+    /// Maximum total size in bytes of the recorded admission reviews
+    #[arg(long, env = "ADMISSION_HISTORY_MAX_BYTES", default_value = "10485760")]
+    pub admission_history_max_bytes: usize,
+
+    /// Enable the unauthenticated POST/DELETE /__health/{probe} endpoints that make /livez
+    /// and /readyz fail on demand
+    #[arg(long, env = "ENABLE_PROBE_CONTROL")]
+    pub enable_probe_control: bool,
+
+    /// Seconds after startup before /readyz starts passing
+    #[arg(long, env = "STARTUP_DELAY_SECS", default_value = "0")]
+    pub startup_delay_secs: u64,
+
+    /// Seconds to let in-flight requests finish after SIGTERM before closing connections
+    #[arg(long, env = "SHUTDOWN_GRACE_PERIOD_SECS", default_value = "30")]
+    pub shutdown_grace_period_secs: u64,
+
+    /// Seconds to keep serving with /readyz failing after SIGTERM, before draining starts
+    #[arg(long, env = "PRE_STOP_DELAY_SECS", default_value = "0")]
+    pub pre_stop_delay_secs: u64,
+
     /// Perform health check and exit (used by Docker HEALTHCHECK)
     #[arg(long)]
     pub check_health: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Print requests arriving at a running server as they are handled
+    Tail(TailArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct TailArgs {
+    /// Base URL of the server to watch
+    #[arg(default_value = "http://localhost:8080")]
+    pub url: String,
+
+    /// Only show requests with exactly this path
+    #[arg(long)]
+    pub path: Option<String>,
+
+    /// Only show requests whose path starts with this prefix
+    #[arg(long)]
+    pub path_prefix: Option<String>,
+
+    /// Only show requests with this method
+    #[arg(long)]
+    pub method: Option<String>,
+
+    /// Also print request headers and body
+    #[arg(short, long)]
+    pub verbose: bool,
+
+    /// Print each request as a raw JSON line
+    #[arg(long)]
+    pub json: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -120,3 +382,123 @@ impl std::fmt::Display for LogFormat {
         }
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLogFormat {
+    Human,
+    Json,
+    /// Apache Common Log Format
+    Common,
+    /// Apache Combined Log Format
+    Combined,
+    Logfmt,
+}
+
+impl std::str::FromStr for AccessLogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "human" => Ok(AccessLogFormat::Human),
+            "json" => Ok(AccessLogFormat::Json),
+            "common" => Ok(AccessLogFormat::Common),
+            "combined" => Ok(AccessLogFormat::Combined),
+            "logfmt" => Ok(AccessLogFormat::Logfmt),
+            _ => Err(format!(
+                "Invalid access log format: {}. Use 'human', 'json', 'common', 'combined' or 'logfmt'",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for AccessLogFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AccessLogFormat::Human => write!(f, "human"),
+            AccessLogFormat::Json => write!(f, "json"),
+            AccessLogFormat::Common => write!(f, "common"),
+            AccessLogFormat::Combined => write!(f, "combined"),
+            AccessLogFormat::Logfmt => write!(f, "logfmt"),
+        }
+    }
+}
+
+impl From<&LogFormat> for AccessLogFormat {
+    fn from(format: &LogFormat) -> Self {
+        match format {
+            LogFormat::Human => AccessLogFormat::Human,
+            LogFormat::Json => AccessLogFormat::Json,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Never,
+    Hourly,
+    Daily,
+}
+
+impl std::str::FromStr for LogRotation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "never" => Ok(LogRotation::Never),
+            "hourly" => Ok(LogRotation::Hourly),
+            "daily" => Ok(LogRotation::Daily),
+            _ => Err(format!("Invalid log rotation: {}. Use 'never', 'hourly' or 'daily'", s)),
+        }
+    }
+}
+
+impl std::fmt::Display for LogRotation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LogRotation::Never => write!(f, "never"),
+            LogRotation::Hourly => write!(f, "hourly"),
+            LogRotation::Daily => write!(f, "daily"),
+        }
+    }
+}
+
+/// How the HTTPS listener treats client certificates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientAuth {
+    /// Don't ask for a client certificate
+    None,
+    /// Verify a client certificate if one is sent, but accept connections without one
+    Optional,
+    /// Reject handshakes without a valid client certificate
+    Required,
+}
+
+impl std::str::FromStr for ClientAuth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(ClientAuth::None),
+            "optional" => Ok(ClientAuth::Optional),
+            "required" => Ok(ClientAuth::Required),
+            _ => Err(format!(
+                "Invalid client auth mode: {}. Use 'none', 'optional' or 'required'",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for ClientAuth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientAuth::None => write!(f, "none"),
+            ClientAuth::Optional => write!(f, "optional"),
+            ClientAuth::Required => write!(f, "required"),
+        }
+    }
+}
@@ -0,0 +1,381 @@
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{self, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use axum_server::accept::Accept;
+use bytes::Bytes;
+use futures_util::{StreamExt, stream};
+use hyper_util::{
+    rt::{TokioExecutor, TokioTimer},
+    server::conn::auto::Builder,
+};
+use std::{
+    future::{Ready, ready},
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    str::FromStr,
+    sync::{
+        Arc,
+        atomic::{AtomicU8, Ordering},
+    },
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::TcpStream,
+};
+use tower::Service;
+use tracing::debug;
+
+use crate::{
+    body::{cut_off, exact_len},
+    config::Config,
+    handlers::{AppState, request_control},
+};
+
+/// Connection-level failure requested with `x-set-connection-fault`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionFault {
+    /// Close the connection (FIN) without sending a response
+    Abort,
+    /// Reset the connection (RST) without sending a response
+    Reset,
+    /// Send the headers and half the body, then close the connection
+    Truncate,
+    /// Send the whole body with a `content-length` larger than its size, then close
+    ContentLength,
+}
+
+impl FromStr for ConnectionFault {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "abort" => Ok(ConnectionFault::Abort),
+            "reset" => Ok(ConnectionFault::Reset),
+            "truncate" => Ok(ConnectionFault::Truncate),
+            "content-length" => Ok(ConnectionFault::ContentLength),
+            _ => Err(format!(
+                "Invalid connection fault: {}. Use 'abort', 'reset', 'truncate' or 'content-length'",
+                s
+            )),
+        }
+    }
+}
+
+/// Listener a request arrived on, inserted as a request extension by the server.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Listener {
+    #[default]
+    Http,
+    Https,
+}
+
+impl Listener {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Listener::Http => "http",
+            Listener::Https => "https",
+        }
+    }
+}
+
+impl ConnectionFault {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConnectionFault::Abort => "connection-abort",
+            ConnectionFault::Reset => "connection-reset",
+            ConnectionFault::Truncate => "truncate",
+            ConnectionFault::ContentLength => "content-length",
+        }
+    }
+}
+
+const NONE: u8 = 0;
+const ABORT: u8 = 1;
+const RESET: u8 = 2;
+
+/// Shared switch between a request handler and the socket its connection runs on. Handlers
+/// find it in the request extensions when the listener uses [`FaultAcceptor`].
+#[derive(Debug, Clone, Default)]
+pub struct ConnectionHandle(Arc<AtomicU8>);
+
+impl ConnectionHandle {
+    /// Fails every subsequent write on the connection, so nothing more reaches the client.
+    /// With `reset` the socket is closed with RST instead of FIN.
+    pub fn kill(&self, reset: bool) {
+        self.0.store(if reset { RESET } else { ABORT }, Ordering::SeqCst);
+    }
+
+    fn state(&self) -> u8 {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+/// Local address of the socket a request arrived on, inserted by [`FaultAcceptor`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalAddr(pub SocketAddr);
+
+/// Listener hook that wraps each accepted TCP stream so handlers can kill the underlying
+/// connection. Use it directly for plain HTTP or as the inner acceptor of the TLS acceptor.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FaultAcceptor;
+
+impl<S> Accept<TcpStream, S> for FaultAcceptor {
+    type Stream = FaultyStream;
+    type Service = WithConnectionHandle<S>;
+    type Future = Ready<io::Result<(Self::Stream, Self::Service)>>;
+
+    fn accept(&self, stream: TcpStream, service: S) -> Self::Future {
+        let handle = ConnectionHandle::default();
+        let local_addr = stream.local_addr().ok().map(LocalAddr);
+        let service = WithConnectionHandle { inner: service, handle: handle.clone(), local_addr };
+        ready(Ok((FaultyStream { inner: stream, handle }, service)))
+    }
+}
+
+/// Per-connection service that exposes the [`ConnectionHandle`] and the [`LocalAddr`] to
+/// every request.
+#[derive(Debug, Clone)]
+pub struct WithConnectionHandle<S> {
+    inner: S,
+    handle: ConnectionHandle,
+    local_addr: Option<LocalAddr>,
+}
+
+impl<S, B> Service<http::Request<B>> for WithConnectionHandle<S>
+where
+    S: Service<http::Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: http::Request<B>) -> Self::Future {
+        request.extensions_mut().insert(self.handle.clone());
+        if let Some(local_addr) = self.local_addr {
+            request.extensions_mut().insert(local_addr);
+        }
+        self.inner.call(request)
+    }
+}
+
+/// TCP stream whose writes fail once its [`ConnectionHandle`] has been killed.
+#[derive(Debug)]
+pub struct FaultyStream {
+    inner: TcpStream,
+    handle: ConnectionHandle,
+}
+
+impl FaultyStream {
+    fn check(&self) -> io::Result<()> {
+        match self.handle.state() {
+            NONE => Ok(()),
+            state => {
+                if state == RESET {
+                    // A zero linger makes the kernel send RST when the socket is dropped
+                    socket2::SockRef::from(&self.inner).set_linger(Some(Duration::ZERO))?;
+                }
+                Err(io::Error::new(io::ErrorKind::ConnectionAborted, "injected connection fault"))
+            },
+        }
+    }
+}
+
+impl AsyncRead for FaultyStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for FaultyStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        self.check()?;
+        Pin::new(&mut self.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.check()?;
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        self.check()?;
+        Pin::new(&mut self.inner).poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+}
+
+/// Smallest HTTP/1 read buffer hyper accepts.
+const MIN_BUF_SIZE: usize = 8192;
+
+/// Room left in the HTTP/1 read buffer for the request line on top of the header limit.
+const REQUEST_LINE_ALLOWANCE: usize = 8192;
+
+/// HTTP/2 counts 32 bytes of overhead per header towards the header list size.
+const HTTP2_HEADER_OVERHEAD: usize = 32;
+
+/// Applies header limits, keep-alive, timeouts and HTTP/2 stream settings to a listener's
+/// connection builder.
+///
+/// HTTP/1 requests whose headers overflow the read buffer or exceed `--max-headers` are
+/// answered with 431 by hyper, and malformed requests with 400. [`limit_headers`] enforces
+/// the exact limits for both protocols.
+pub fn configure_http(builder: &mut Builder<TokioExecutor>, config: &Config) {
+    let header_read_timeout = Some(Duration::from_secs(config.header_read_timeout_secs))
+        .filter(|timeout| !timeout.is_zero());
+
+    builder
+        .http1()
+        .timer(TokioTimer::new())
+        .keep_alive(!config.disable_keep_alive)
+        .max_headers(config.max_headers)
+        .max_buf_size((config.max_header_size + REQUEST_LINE_ALLOWANCE).max(MIN_BUF_SIZE))
+        .header_read_timeout(header_read_timeout);
+
+    let header_list_size = config.max_header_size + HTTP2_HEADER_OVERHEAD * config.max_headers;
+    builder
+        .http2()
+        .timer(TokioTimer::new())
+        .max_concurrent_streams(config.http2_max_concurrent_streams)
+        .max_header_list_size(u32::try_from(header_list_size).unwrap_or(u32::MAX))
+        .initial_stream_window_size(config.http2_stream_window_size)
+        .initial_connection_window_size(config.http2_connection_window_size)
+        .keep_alive_interval(config.http2_keep_alive_interval_secs.map(Duration::from_secs))
+        .keep_alive_timeout(Duration::from_secs(config.http2_keep_alive_timeout_secs));
+}
+
+/// Middleware that rejects requests with more than `--max-headers` headers or more than
+/// `--max-header-size` bytes of header names and values with 431.
+pub async fn limit_headers(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let headers = request.headers();
+    let size: usize = headers.iter().map(|(name, value)| name.as_str().len() + value.len()).sum();
+
+    if headers.len() > state.config.max_headers || size > state.config.max_header_size {
+        debug!("Rejecting request with {} headers totalling {} bytes", headers.len(), size);
+        return (StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE, "Request header fields too large")
+            .into_response();
+    }
+
+    next.run(request).await
+}
+
+/// Middleware that applies the `x-set-connection-fault` control.
+///
+/// `abort` and `reset` need the [`FaultAcceptor`] listener hook; without it they fall back to
+/// failing the response body, which still sends the status line and headers.
+pub async fn inject_connection_fault(request: Request, next: Next) -> Response {
+    let (parts, body) = request.into_parts();
+    let control = request_control::<String>(&parts, "x-set-connection-fault");
+    let request = Request::from_parts(parts, body);
+
+    let fault = match control.map(|value| value.parse::<ConnectionFault>()) {
+        Some(Ok(fault)) => fault,
+        Some(Err(e)) => return (StatusCode::BAD_REQUEST, e).into_response(),
+        None => return next.run(request).await,
+    };
+
+    debug!("Injecting connection fault {:?}", fault);
+    #[cfg(feature = "prometheus")]
+    crate::metrics::record_fault(fault.as_str());
+
+    match fault {
+        ConnectionFault::Abort | ConnectionFault::Reset => {
+            match request.extensions().get::<ConnectionHandle>() {
+                Some(handle) => handle.kill(fault == ConnectionFault::Reset),
+                None => return failing_response(),
+            }
+            Response::new(Body::empty())
+        },
+        ConnectionFault::Truncate | ConnectionFault::ContentLength => {
+            let response = next.run(request).await;
+            let (mut parts, body) = response.into_parts();
+
+            // Bodies of unknown size are treated as if they ended after their first chunk
+            let (body, len) = match exact_len(&parts.headers, &body) {
+                Some(len) => (body, len),
+                None => first_chunk(body).await,
+            };
+
+            let (declared, sent) = match fault {
+                ConnectionFault::Truncate => (len, len / 2),
+                _ => (len.saturating_mul(2).saturating_add(1), len),
+            };
+
+            // The server closes the connection when the body ends short of content-length
+            parts.headers.insert("content-length", HeaderValue::from(declared));
+            parts.extensions.insert(SkipCompression);
+
+            Response::from_parts(parts, cut_off(body, Some(sent), None))
+        },
+    }
+}
+
+/// Pulls the first non-empty chunk of `body`, returning the reassembled body and the chunk's
+/// length.
+async fn first_chunk(body: Body) -> (Body, u64) {
+    let mut inner = body.into_data_stream();
+    let first = loop {
+        match inner.next().await {
+            Some(Ok(bytes)) if bytes.is_empty() => continue,
+            Some(Ok(bytes)) => break bytes,
+            Some(Err(_)) | None => break Bytes::new(),
+        }
+    };
+
+    let len = first.len() as u64;
+    (Body::from_stream(stream::once(ready(Ok::<_, axum::Error>(first))).chain(inner)), len)
+}
+
+/// Response extension marking a body whose exact bytes or pacing matter, such as deliberately
+/// broken framing or a generated payload, so the compression layer leaves it as it is.
+#[derive(Debug, Clone, Copy)]
+pub struct SkipCompression;
+
+/// Compression predicate honouring [`SkipCompression`].
+pub fn allow_compression(
+    _: StatusCode,
+    _: http::Version,
+    _: &http::HeaderMap,
+    extensions: &http::Extensions,
+) -> bool {
+    extensions.get::<SkipCompression>().is_none()
+}
+
+fn failing_response() -> Response {
+    let body =
+        stream::iter([Err::<Bytes, io::Error>(io::Error::other("injected connection fault"))]);
+    Response::new(Body::from_stream(body))
+}
@@ -14,9 +14,22 @@ pub enum AppError {
     #[error("Server error: {0}")]
     Server(String),
 
+    #[error("Rules file error: {0}")]
+    Rules(String),
+
+    #[error("Logging configuration error: {0}")]
+    Logging(String),
+
+    #[error("Admission rules error: {0}")]
+    Admission(String),
+
     #[cfg(feature = "jwt")]
     #[error("JWT decode error: {0}")]
     JwtDecode(#[from] jsonwebtoken::errors::Error),
+
+    #[cfg(feature = "jwt")]
+    #[error("OIDC configuration error: {0}")]
+    Oidc(String),
 }
 
 pub type Result<T> = std::result::Result<T, AppError>;
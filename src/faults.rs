@@ -0,0 +1,104 @@
+use axum::{
+    Json,
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use std::sync::Mutex;
+use tracing::debug;
+
+use crate::{
+    body::{cut_off, exact_len},
+    handlers::{AppState, request_control},
+};
+
+/// Shared random source for probabilistic fault injection. Seeding it makes the sequence of
+/// injected faults reproducible for a given order of requests.
+pub struct FaultInjector {
+    rng: Mutex<StdRng>,
+}
+
+impl FaultInjector {
+    pub fn new(seed: Option<u64>) -> Self {
+        let rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_os_rng(),
+        };
+
+        FaultInjector { rng: Mutex::new(rng) }
+    }
+
+    /// Returns true with the given probability (clamped to 0.0-1.0).
+    pub fn roll(&self, rate: f64) -> bool {
+        if rate <= 0.0 || rate.is_nan() {
+            return false;
+        }
+
+        let sample: f64 = self.with_rng(|rng| rng.random());
+        sample < rate.min(1.0)
+    }
+
+    /// Runs `f` with exclusive access to the shared random source.
+    pub fn with_rng<T>(&self, f: impl FnOnce(&mut StdRng) -> T) -> T {
+        f(&mut self.rng.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+}
+
+/// Middleware that fails a fraction of requests with an error status or drops the
+/// connection halfway through the response body.
+///
+/// Rates come from `x-set-response-error-rate` / `x-set-response-abort-rate` (query or
+/// header) and fall back to the global `--error-rate` / `--abort-rate` flags.
+pub async fn inject_faults(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let (parts, body) = request.into_parts();
+
+    let error_rate =
+        request_control(&parts, "x-set-response-error-rate").or(state.config.error_rate);
+    let abort_rate =
+        request_control(&parts, "x-set-response-abort-rate").or(state.config.abort_rate);
+
+    if let Some(rate) = error_rate {
+        if state.faults.roll(rate) {
+            let status = request_control(&parts, "x-set-response-error-code")
+                .and_then(|code| StatusCode::from_u16(code).ok())
+                .or_else(|| StatusCode::from_u16(state.config.error_code).ok())
+                .unwrap_or(StatusCode::SERVICE_UNAVAILABLE);
+
+            debug!("Injecting {} error for {}", status, parts.uri.path());
+            #[cfg(feature = "prometheus")]
+            crate::metrics::record_fault("error");
+            let body = serde_json::json!({ "error": "injected fault", "status": status.as_u16() });
+            return (status, Json(body)).into_response();
+        }
+    }
+
+    let abort = abort_rate.is_some_and(|rate| state.faults.roll(rate));
+    let response = next.run(Request::from_parts(parts, body)).await;
+
+    if abort {
+        debug!("Injecting connection abort");
+        #[cfg(feature = "prometheus")]
+        crate::metrics::record_fault("abort");
+        return abort_response(response);
+    }
+
+    response
+}
+
+/// Sends the response headers and the first half of the body (or its first chunk when the
+/// size is unknown), then fails the body stream so the server drops the connection
+/// (HTTP/1.1) or resets the stream (HTTP/2).
+fn abort_response(response: Response) -> Response {
+    let (mut parts, body) = response.into_parts();
+    let half = exact_len(&parts.headers, &body).map(|len| len / 2);
+
+    parts.headers.remove("content-length");
+
+    Response::from_parts(parts, cut_off(body, half, Some("injected connection abort")))
+}
@@ -2,35 +2,125 @@ use axum::{
     Json,
     body::Body,
     extract::{ConnectInfo, Query, Request, State},
-    http::{HeaderMap, HeaderValue, StatusCode},
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode, request::Parts},
     response::{IntoResponse, Response},
 };
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::{collections::HashMap, net::SocketAddr, sync::Arc, time::Duration};
-use tokio::time::sleep;
+use std::{collections::HashMap, net::SocketAddr, str::FromStr, sync::Arc, time::Duration};
+
+use crate::{
+    access_log::AccessLog,
+    admission::AdmissionController,
+    config::Config,
+    connection::{Listener, LocalAddr},
+    error,
+    faults::FaultInjector,
+    health::{self, Health, Probe},
+    history::{History, RecordedRequest},
+    kubernetes::PodInfo,
+    load::LoadGenerator,
+    payload::Payload,
+    resources::ResourceInfo,
+    rules::{MatchedRule, RuleSet},
+    template,
+    tls::TlsInfo,
+};
 
-use crate::config::Config;
+#[cfg(feature = "mtls")]
+use crate::client_cert::{ClientCertInfo, extract_client_cert};
+#[cfg(feature = "jwt")]
+use crate::{
+    jwt::{self, JwtInfo, JwtVerifier, Verification, extract_jwt},
+    oidc::MockIssuer,
+};
 
 #[derive(Clone)]
 pub struct AppState {
     pub config: Arc<Config>,
     pub hostname: String,
+    pub rules: Arc<RuleSet>,
+    pub admission: Arc<AdmissionController>,
+    pub faults: Arc<FaultInjector>,
+    pub history: Arc<History>,
+    pub access_log: Arc<AccessLog>,
+    pub health: Arc<Health>,
+    pub kubernetes: Option<Arc<PodInfo>>,
+    pub load: Arc<LoadGenerator>,
+    #[cfg(feature = "jwt")]
+    pub jwt: Option<Arc<JwtVerifier>>,
+    #[cfg(feature = "jwt")]
+    pub oidc: Option<Arc<MockIssuer>>,
+}
+
+impl AppState {
+    pub fn new(config: Config, hostname: String) -> error::Result<Self> {
+        let rules = match &config.rules_file {
+            Some(path) => RuleSet::load(path)?,
+            None => RuleSet::default(),
+        };
+
+        let admission = AdmissionController::load(
+            config.admission_rules_file.as_deref(),
+            config.admission_history_size,
+            config.admission_history_max_bytes,
+        )?;
+        let faults = FaultInjector::new(config.fault_seed);
+        let history = History::new(config.history_size, config.history_max_bytes);
+        let access_log = AccessLog::new(&config)?;
+        let health = Health::new(Duration::from_secs(config.startup_delay_secs));
+        let kubernetes = PodInfo::load(&config.podinfo_dir).map(Arc::new);
+        #[cfg(feature = "jwt")]
+        let oidc = MockIssuer::from_config(&config)?.map(Arc::new);
+        #[cfg(feature = "jwt")]
+        let jwt = JwtVerifier::from_config(&config, oidc.as_deref())?.map(Arc::new);
+        let load = LoadGenerator::new(
+            config.load_max_cores,
+            config.load_max_memory,
+            Duration::from_secs(config.load_max_duration_secs),
+        );
+
+        Ok(AppState {
+            config: Arc::new(config),
+            hostname,
+            rules: Arc::new(rules),
+            admission: Arc::new(admission),
+            faults: Arc::new(faults),
+            history: Arc::new(history),
+            access_log: Arc::new(access_log),
+            health: Arc::new(health),
+            kubernetes,
+            load: Arc::new(load),
+            #[cfg(feature = "jwt")]
+            jwt,
+            #[cfg(feature = "jwt")]
+            oidc,
+        })
+    }
 }
 
 // Health check handlers
-pub async fn liveness_handler() -> &'static str {
-    "OK"
+pub async fn liveness_handler(State(state): State<AppState>) -> Response {
+    let status = health::probe(&state.health, Probe::Liveness).await;
+    if !status.healthy {
+        return (StatusCode::SERVICE_UNAVAILABLE, "FAIL").into_response();
+    }
+
+    "OK".into_response()
 }
 
-pub async fn readiness_handler() -> Json<Value> {
-    // In production, you would check dependencies here:
-    // - Database connectivity
-    // - Cache availability
-    // - External service health
+pub async fn readiness_handler(State(state): State<AppState>) -> Response {
+    let status = health::probe(&state.health, Probe::Readiness).await;
+    if let Some(reason) = status.reason {
+        let body = serde_json::json!({ "status": "not ready", "reason": reason });
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(body)).into_response();
+    }
+
     Json(serde_json::json!({
         "status": "ready"
     }))
+    .into_response()
 }
 
 #[derive(Debug, Deserialize)]
@@ -38,9 +128,6 @@ pub struct EchoQueryParams {
     #[serde(rename = "x-set-response-status-code")]
     response_status_code: Option<u16>,
 
-    #[serde(rename = "x-set-response-delay-ms")]
-    response_delay_ms: Option<u64>,
-
     #[serde(rename = "x-set-response-content-type")]
     response_content_type: Option<String>,
 
@@ -68,6 +155,10 @@ pub struct EchoResponse {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub os: Option<OsInfo>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub kubernetes: Option<Arc<PodInfo>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resources: Option<ResourceInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub connection: Option<ConnectionInfo>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub json: Option<Value>,
@@ -88,23 +179,20 @@ pub struct OsInfo {
 
 #[derive(Debug, Serialize)]
 pub struct ConnectionInfo {
+    /// `http` or `https`
+    pub scheme: &'static str,
+    /// Server name the client sent via TLS SNI
     pub servername: Option<String>,
-}
-
-#[cfg(feature = "jwt")]
-#[derive(Debug, Serialize)]
-pub struct JwtInfo {
-    pub header: Option<Value>,
-    pub payload: Option<Value>,
-}
-
-#[cfg(feature = "mtls")]
-#[derive(Debug, Serialize)]
-pub struct ClientCertInfo {
-    pub subject: String,
-    pub issuer: String,
-    pub subjectaltname: Option<String>,
-    pub info: Option<String>,
+    pub local_address: Option<String>,
+    pub local_port: Option<u16>,
+    pub client_port: u16,
+    /// Negotiated TLS version, e.g. `TLSv1.3`
+    pub tls_version: Option<String>,
+    pub cipher_suite: Option<String>,
+    /// Protocol agreed via ALPN, e.g. `h2`
+    pub alpn_protocol: Option<String>,
+    /// Whether the TLS session was resumed rather than fully negotiated
+    pub session_resumed: Option<bool>,
 }
 
 pub async fn echo_handler(
@@ -113,17 +201,98 @@ pub async fn echo_handler(
     Query(query_params): Query<EchoQueryParams>,
     request: Request,
 ) -> Response {
-    // Extract request parts before consuming the body
+    #[cfg(feature = "jwt")]
+    let (mut parts, body) = request.into_parts();
+    #[cfg(not(feature = "jwt"))]
     let (parts, body) = request.into_parts();
+
+    // Verify the token once for both the rejection check and the echoed `jwt`
+    #[cfg(feature = "jwt")]
+    if let Some(verification) =
+        jwt::verify_request(&parts.headers, &state.config, state.jwt.as_deref())
+    {
+        parts.extensions.insert(verification);
+    }
+
+    let mut echo = LazyEcho { body: Some(body), echo: None };
+    let mut response = respond(&state, addr, query_params, &parts, &mut echo).await;
+
+    // Hand the echoed request to the history middleware so it doesn't rebuild it
+    if state.history.is_observed() {
+        let echo_response = echo.get(&state, addr, &parts).await;
+        let request = serde_json::to_value(echo_response).unwrap_or(Value::Null);
+        response.extensions_mut().insert(RecordedRequest(request));
+    }
+
+    response
+}
+
+/// Echo response built on first use and then shared, so the request body is read and the
+/// response assembled at most once per request.
+struct LazyEcho {
+    body: Option<Body>,
+    echo: Option<EchoResponse>,
+}
+
+impl LazyEcho {
+    async fn get(&mut self, state: &AppState, addr: SocketAddr, parts: &Parts) -> &EchoResponse {
+        let echo = match self.echo.take() {
+            Some(echo) => echo,
+            None => {
+                let body = self.body.take().unwrap_or_default();
+                build_echo_response(state, addr, parts, body).await
+            },
+        };
+        self.echo.insert(echo)
+    }
+}
+
+async fn respond(
+    state: &AppState,
+    addr: SocketAddr,
+    query_params: EchoQueryParams,
+    parts: &Parts,
+    echo: &mut LazyEcho,
+) -> Response {
     let headers = &parts.headers;
-    let method = parts.method.to_string();
-    let uri = &parts.uri;
-    let path = uri.path().to_string();
-    let protocol = format!("{:?}", parts.version);
+
+    #[cfg(feature = "jwt")]
+    if let Some(response) =
+        jwt::reject_invalid(parts.extensions.get::<Verification>(), state.jwt.as_deref())
+    {
+        return response;
+    }
+
+    // Extract arbitrary response headers
+    let response_headers = extract_response_headers(headers, parts.uri.query());
+
+    // Serve the first matching mock rule, if any
+    if let Some(rule) = state.rules.find(parts) {
+        let context = if rule.response.template {
+            Some(template_context(echo.get(state, addr, parts).await))
+        } else {
+            None
+        };
+
+        let mut response = rule.respond(context.as_ref()).await;
+        response.headers_mut().extend(response_headers);
+        response
+            .extensions_mut()
+            .insert(MatchedRule(rule.name.clone().unwrap_or_else(|| "unnamed".to_string())));
+        return response;
+    }
 
     // Check if we should override response with file content
     if let Some(file_path) = &state.config.override_response_body_file_path {
-        return serve_file(file_path).await;
+        let context = if state.config.override_response_body_template {
+            Some(template_context(echo.get(state, addr, parts).await))
+        } else {
+            None
+        };
+
+        let mut response = serve_file(file_path, context.as_ref()).await;
+        response.headers_mut().extend(response_headers);
+        return response;
     }
 
     // Extract status code override
@@ -138,14 +307,6 @@ pub async fn echo_handler(
         .and_then(|code| StatusCode::from_u16(code).ok())
         .unwrap_or(StatusCode::OK);
 
-    // Extract delay
-    let delay_ms = query_params.response_delay_ms.or_else(|| {
-        headers
-            .get("x-set-response-delay-ms")
-            .and_then(|v| v.to_str().ok())
-            .and_then(|v| v.parse().ok())
-    });
-
     // Extract custom content-type
     let content_type = query_params.response_content_type.or_else(|| {
         headers
@@ -154,13 +315,160 @@ pub async fn echo_handler(
             .map(|v| v.to_string())
     });
 
-    // Apply delay if requested
-    if let Some(delay) = delay_ms {
-        sleep(Duration::from_millis(delay)).await;
+    // Generate a synthetic payload if requested
+    match Payload::from_request(parts) {
+        Ok(Some(payload)) => {
+            let seed = state.faults.with_rng(|rng| rng.random());
+            let mut response = payload.into_response(seed);
+            *response.status_mut() = status_code;
+
+            if let Some(ct) = content_type {
+                if let Ok(header_value) = HeaderValue::from_str(&ct) {
+                    response.headers_mut().insert("content-type", header_value);
+                }
+            }
+
+            response.headers_mut().extend(response_headers);
+            return response;
+        },
+        Ok(None) => {},
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    }
+
+    // Build echo response
+    let echo_response = echo.get(state, addr, parts).await;
+
+    // Check if echo back to client is disabled
+    if let Some(false) = state.config.echo_back_to_client {
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = status_code;
+        response.headers_mut().extend(response_headers);
+        return response;
+    }
+
+    // Check if response_body_only is requested
+    if query_params.response_body_only.unwrap_or(false) {
+        let mut response = Response::new(Body::from(echo_response.body.clone()));
+        *response.status_mut() = status_code;
+
+        if let Some(ct) = content_type {
+            if let Ok(header_value) = HeaderValue::from_str(&ct) {
+                response.headers_mut().insert("content-type", header_value);
+            }
+        }
+
+        response.headers_mut().extend(response_headers);
+        return response;
     }
 
+    // Build JSON response
+    let json_response = Json(echo_response);
+    let mut response = json_response.into_response();
+    *response.status_mut() = status_code;
+
+    // Apply custom content-type if specified
+    if let Some(ct) = content_type {
+        if let Ok(header_value) = HeaderValue::from_str(&ct) {
+            response.headers_mut().insert("content-type", header_value);
+        }
+    }
+
+    // Apply CORS headers if configured
+    if let Some(origin) = &state.config.cors_allow_origin {
+        if let Ok(header_value) = HeaderValue::from_str(origin) {
+            response.headers_mut().insert("access-control-allow-origin", header_value);
+        }
+
+        if let Some(methods) = &state.config.cors_allow_methods {
+            if let Ok(header_value) = HeaderValue::from_str(methods) {
+                response.headers_mut().insert("access-control-allow-methods", header_value);
+            }
+        }
+
+        if let Some(headers_val) = &state.config.cors_allow_headers {
+            if let Ok(header_value) = HeaderValue::from_str(headers_val) {
+                response.headers_mut().insert("access-control-allow-headers", header_value);
+            }
+        }
+
+        if let Some(credentials) = &state.config.cors_allow_credentials {
+            if let Ok(header_value) = HeaderValue::from_str(credentials) {
+                response.headers_mut().insert("access-control-allow-credentials", header_value);
+            }
+        }
+    }
+
+    // Apply arbitrary response headers last so they take precedence
+    response.headers_mut().extend(response_headers);
+
+    response
+}
+
+/// Collects response headers requested via `x-set-response-header-<Name>=<value>` query
+/// parameters and repeated `x-set-response-header: <Name>: <value>` request headers.
+fn extract_response_headers(headers: &HeaderMap, query: Option<&str>) -> HeaderMap {
+    let mut response_headers = HeaderMap::new();
+
+    let from_query = query
+        .map(|q| {
+            url::form_urlencoded::parse(q.as_bytes())
+                .filter_map(|(k, v)| {
+                    k.strip_prefix("x-set-response-header-")
+                        .map(|name| (name.to_string(), v.to_string()))
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+
+    let from_headers = headers
+        .get_all("x-set-response-header")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .filter_map(|v| v.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()));
+
+    for (name, value) in from_query.into_iter().chain(from_headers) {
+        if let (Ok(name), Ok(value)) =
+            (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(&value))
+        {
+            response_headers.append(name, value);
+        }
+    }
+
+    response_headers
+}
+
+/// Reads a response control from the query string, falling back to the request header of the
+/// same name.
+pub fn request_control<T: FromStr>(parts: &Parts, name: &str) -> Option<T> {
+    parts
+        .uri
+        .query()
+        .and_then(|q| {
+            url::form_urlencoded::parse(q.as_bytes())
+                .find(|(k, _)| k == name)
+                .and_then(|(_, v)| v.parse().ok())
+        })
+        .or_else(|| {
+            parts.headers.get(name).and_then(|v| v.to_str().ok()).and_then(|v| v.parse().ok())
+        })
+}
+
+/// Builds the echo response for a request, consuming its body.
+pub async fn build_echo_response(
+    state: &AppState,
+    addr: SocketAddr,
+    parts: &Parts,
+    body: Body,
+) -> EchoResponse {
+    let headers = &parts.headers;
+    let method = parts.method.to_string();
+    let path = parts.uri.path().to_string();
+    let protocol = format!("{:?}", parts.version);
+
     // Parse query parameters
-    let query: HashMap<String, String> = uri
+    let query: HashMap<String, String> = parts
+        .uri
         .query()
         .map(|q| {
             url::form_urlencoded::parse(q.as_bytes())
@@ -225,10 +533,19 @@ pub async fn echo_handler(
     let os_info = Some(OsInfo { hostname: state.hostname.clone() });
 
     // Connection info
-    let connection_info = headers
-        .get("host")
-        .and_then(|v| v.to_str().ok())
-        .map(|host| ConnectionInfo { servername: Some(host.to_string()) });
+    let local_addr = parts.extensions.get::<LocalAddr>().map(|LocalAddr(addr)| addr);
+    let tls = parts.extensions.get::<TlsInfo>().cloned().unwrap_or_default();
+    let connection_info = Some(ConnectionInfo {
+        scheme: parts.extensions.get::<Listener>().copied().unwrap_or_default().as_str(),
+        servername: tls.servername,
+        local_address: local_addr.map(|addr| addr.ip().to_string()),
+        local_port: local_addr.map(|addr| addr.port()),
+        client_port: addr.port(),
+        tls_version: tls.version,
+        cipher_suite: tls.cipher_suite,
+        alpn_protocol: tls.alpn_protocol,
+        session_resumed: tls.resumed,
+    });
 
     // Environment variables
     let environment =
@@ -236,23 +553,24 @@ pub async fn echo_handler(
 
     // JWT decoding
     #[cfg(feature = "jwt")]
-    let jwt = extract_jwt(headers, &state.config);
-
-    #[cfg(not(feature = "jwt"))]
-    let jwt = None;
+    let jwt = {
+        let verification = parts
+            .extensions
+            .get::<Verification>()
+            .cloned()
+            .or_else(|| jwt::verify_request(headers, &state.config, state.jwt.as_deref()));
+        extract_jwt(headers, &state.config, verification)
+    };
 
     // mTLS client cert info
     #[cfg(feature = "mtls")]
-    let client_cert = extract_client_cert(headers);
-
-    #[cfg(not(feature = "mtls"))]
-    let client_cert = None;
+    let client_cert = extract_client_cert(headers, parts.extensions.get::<TlsInfo>());
 
-    let echo_response = EchoResponse {
+    EchoResponse {
         path,
         headers: headers_map,
         method,
-        body: body_str.clone(),
+        body: body_str,
         cookies,
         fresh: false,
         hostname: state.hostname.clone(),
@@ -263,6 +581,8 @@ pub async fn echo_handler(
         subdomains,
         xhr,
         os: os_info,
+        kubernetes: state.kubernetes.clone(),
+        resources: state.config.include_resources.then(ResourceInfo::current),
         connection: connection_info,
         json: json_body,
         environment,
@@ -270,67 +590,12 @@ pub async fn echo_handler(
         jwt,
         #[cfg(feature = "mtls")]
         client_cert,
-    };
-
-    // Check if echo back to client is disabled
-    if let Some(false) = state.config.echo_back_to_client {
-        let mut response = Response::new(Body::empty());
-        *response.status_mut() = status_code;
-        return response;
-    }
-
-    // Check if response_body_only is requested
-    if query_params.response_body_only.unwrap_or(false) {
-        let mut response = Response::new(Body::from(body_str));
-        *response.status_mut() = status_code;
-
-        if let Some(ct) = content_type {
-            if let Ok(header_value) = HeaderValue::from_str(&ct) {
-                response.headers_mut().insert("content-type", header_value);
-            }
-        }
-
-        return response;
-    }
-
-    // Build JSON response
-    let json_response = Json(echo_response);
-    let mut response = json_response.into_response();
-    *response.status_mut() = status_code;
-
-    // Apply custom content-type if specified
-    if let Some(ct) = content_type {
-        if let Ok(header_value) = HeaderValue::from_str(&ct) {
-            response.headers_mut().insert("content-type", header_value);
-        }
-    }
-
-    // Apply CORS headers if configured
-    if let Some(origin) = &state.config.cors_allow_origin {
-        if let Ok(header_value) = HeaderValue::from_str(origin) {
-            response.headers_mut().insert("access-control-allow-origin", header_value);
-        }
-
-        if let Some(methods) = &state.config.cors_allow_methods {
-            if let Ok(header_value) = HeaderValue::from_str(methods) {
-                response.headers_mut().insert("access-control-allow-methods", header_value);
-            }
-        }
-
-        if let Some(headers_val) = &state.config.cors_allow_headers {
-            if let Ok(header_value) = HeaderValue::from_str(headers_val) {
-                response.headers_mut().insert("access-control-allow-headers", header_value);
-            }
-        }
-
-        if let Some(credentials) = &state.config.cors_allow_credentials {
-            if let Ok(header_value) = HeaderValue::from_str(credentials) {
-                response.headers_mut().insert("access-control-allow-credentials", header_value);
-            }
-        }
     }
+}
 
-    response
+/// Serializes an echo response into the context used for templated bodies.
+fn template_context(echo_response: &EchoResponse) -> Value {
+    serde_json::to_value(echo_response).unwrap_or(Value::Null)
 }
 
 fn extract_ips(headers: &HeaderMap, default_ip: &str) -> Vec<String> {
@@ -347,54 +612,6 @@ fn extract_ips(headers: &HeaderMap, default_ip: &str) -> Vec<String> {
     ips
 }
 
-#[cfg(feature = "jwt")]
-fn extract_jwt(headers: &HeaderMap, config: &Config) -> Option<JwtInfo> {
-    let jwt_header = config.jwt_header.as_deref().unwrap_or("authorization");
-
-    let token = headers
-        .get(jwt_header)
-        .and_then(|v| v.to_str().ok())
-        .map(|v| if v.to_lowercase().starts_with("bearer ") { &v[7..] } else { v })?;
-
-    let header = jsonwebtoken::decode_header(token).ok()?;
-
-    // Decode without verification (just for inspection)
-    let payload = if let Ok(token_data) = jsonwebtoken::dangerous::insecure_decode::<Value>(token) {
-        Some(token_data.claims)
-    } else {
-        None
-    };
-
-    Some(JwtInfo { header: serde_json::to_value(header).ok(), payload })
-}
-
-#[cfg(feature = "mtls")]
-fn extract_client_cert(headers: &HeaderMap) -> Option<ClientCertInfo> {
-    // Check for common headers that contain client certificate info
-    // These are typically set by reverse proxies (nginx, envoy, etc.)
-
-    let subject = headers
-        .get("x-client-cert-subject")
-        .or_else(|| headers.get("ssl-client-subject-dn"))
-        .and_then(|v| v.to_str().ok())
-        .map(|s| s.to_string())?;
-
-    let issuer = headers
-        .get("x-client-cert-issuer")
-        .or_else(|| headers.get("ssl-client-issuer-dn"))
-        .and_then(|v| v.to_str().ok())
-        .map(|s| s.to_string())
-        .unwrap_or_default();
-
-    let subjectaltname =
-        headers.get("x-client-cert-san").and_then(|v| v.to_str().ok()).map(|s| s.to_string());
-
-    let info =
-        headers.get("x-client-cert-info").and_then(|v| v.to_str().ok()).map(|s| s.to_string());
-
-    Some(ClientCertInfo { subject, issuer, subjectaltname, info })
-}
-
 #[cfg(feature = "prometheus")]
 pub async fn metrics_handler() -> Response {
     use prometheus::{Encoder, TextEncoder};
@@ -458,11 +675,18 @@ fn extract_subdomains(headers: &HeaderMap) -> Option<Vec<String>> {
     }
 }
 
-async fn serve_file(file_path: &std::path::Path) -> Response {
+async fn serve_file(file_path: &std::path::Path, context: Option<&Value>) -> Response {
     use tokio::fs;
 
     match fs::read(file_path).await {
         Ok(contents) => {
+            let contents = match context {
+                Some(context) => {
+                    template::render(&String::from_utf8_lossy(&contents), context).into_bytes()
+                },
+                None => contents,
+            };
+
             let mime_type = mime_guess::from_path(file_path).first_or_octet_stream().to_string();
 
             Response::builder()
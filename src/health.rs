@@ -0,0 +1,174 @@
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use crate::handlers::AppState;
+
+/// A probe endpoint whose outcome can be controlled at runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Probe {
+    /// `/livez`
+    Liveness,
+    /// `/readyz`
+    Readiness,
+}
+
+/// Changes applied by `POST /__health/{probe}`. Omitted fields are left as they are.
+#[derive(Debug, Default, Deserialize)]
+pub struct ProbeUpdate {
+    /// Pass (`true`) or fail (`false`) until changed again
+    pub healthy: Option<bool>,
+    /// Fail for this many milliseconds, then recover
+    pub fail_for_ms: Option<u64>,
+    /// Delay every probe response by this many milliseconds
+    pub delay_ms: Option<u64>,
+}
+
+/// Current outcome of a probe, as reported by `GET /__health`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProbeStatus {
+    pub healthy: bool,
+    pub reason: Option<&'static str>,
+    pub failing_for_ms: Option<u64>,
+    pub delay_ms: u64,
+}
+
+#[derive(Debug, Default)]
+struct ProbeControl {
+    disabled: bool,
+    fail_until: Option<Instant>,
+    delay: Duration,
+}
+
+/// Probe state shared by `/livez` and `/readyz`.
+#[derive(Debug)]
+pub struct Health {
+    shutting_down: AtomicBool,
+    /// `None` when the startup delay reaches past any representable time
+    ready_at: Option<Instant>,
+    liveness: Mutex<ProbeControl>,
+    readiness: Mutex<ProbeControl>,
+}
+
+impl Health {
+    /// Readiness fails until `startup_delay` has passed.
+    pub fn new(startup_delay: Duration) -> Self {
+        Health {
+            shutting_down: AtomicBool::new(false),
+            ready_at: Instant::now().checked_add(startup_delay),
+            liveness: Mutex::default(),
+            readiness: Mutex::default(),
+        }
+    }
+
+    /// Marks the server as draining so `/readyz` starts failing.
+    pub fn begin_shutdown(&self) {
+        self.shutting_down.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::SeqCst)
+    }
+
+    pub fn status(&self, probe: Probe) -> ProbeStatus {
+        let now = Instant::now();
+        let control = self.lock(probe);
+        let failing_for = control.fail_until.and_then(|until| until.checked_duration_since(now));
+
+        let reason = match probe {
+            Probe::Readiness if self.is_shutting_down() => Some("shutting down"),
+            Probe::Readiness if self.ready_at.is_none_or(|at| now < at) => Some("starting"),
+            _ if control.disabled => Some("disabled"),
+            _ if failing_for.is_some() => Some("failing"),
+            _ => None,
+        };
+
+        ProbeStatus {
+            healthy: reason.is_none(),
+            reason,
+            failing_for_ms: failing_for.map(|d| d.as_millis() as u64),
+            delay_ms: control.delay.as_millis() as u64,
+        }
+    }
+
+    /// Applies an update, rejecting a `fail_for_ms` too far in the future to represent.
+    pub fn update(&self, probe: Probe, update: &ProbeUpdate) -> Result<(), String> {
+        let fail_until = update
+            .fail_for_ms
+            .map(|ms| {
+                Instant::now()
+                    .checked_add(Duration::from_millis(ms))
+                    .ok_or_else(|| format!("fail_for_ms {} is too large", ms))
+            })
+            .transpose()?;
+
+        let mut control = self.lock(probe);
+        if let Some(healthy) = update.healthy {
+            control.disabled = !healthy;
+            if healthy {
+                control.fail_until = None;
+            }
+        }
+        if fail_until.is_some() {
+            control.fail_until = fail_until;
+        }
+        if let Some(ms) = update.delay_ms {
+            control.delay = Duration::from_millis(ms);
+        }
+        Ok(())
+    }
+
+    /// Clears every runtime override of the probe.
+    pub fn reset(&self, probe: Probe) {
+        *self.lock(probe) = ProbeControl::default();
+    }
+
+    fn lock(&self, probe: Probe) -> std::sync::MutexGuard<'_, ProbeControl> {
+        let control = match probe {
+            Probe::Liveness => &self.liveness,
+            Probe::Readiness => &self.readiness,
+        };
+        control.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Waits out the probe's configured delay and returns its outcome.
+pub async fn probe(health: &Health, probe: Probe) -> ProbeStatus {
+    let delay = health.lock(probe).delay;
+    if !delay.is_zero() {
+        tokio::time::sleep(delay).await;
+    }
+    health.status(probe)
+}
+
+pub async fn get_health(State(state): State<AppState>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({
+        "liveness": state.health.status(Probe::Liveness),
+        "readiness": state.health.status(Probe::Readiness),
+    }))
+}
+
+pub async fn update_probe(
+    State(state): State<AppState>,
+    Path(probe): Path<Probe>,
+    Query(update): Query<ProbeUpdate>,
+) -> Result<Json<ProbeStatus>, (StatusCode, String)> {
+    state.health.update(probe, &update).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    Ok(Json(state.health.status(probe)))
+}
+
+pub async fn reset_probe(State(state): State<AppState>, Path(probe): Path<Probe>) -> StatusCode {
+    state.health.reset(probe);
+    StatusCode::NO_CONTENT
+}
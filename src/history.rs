@@ -0,0 +1,273 @@
+use axum::{
+    Json,
+    body::Body,
+    extract::{ConnectInfo, Path, Query, Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{
+        IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+};
+use chrono::{DateTime, Utc};
+use futures_util::{Stream, StreamExt, stream};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::VecDeque,
+    future::ready,
+    net::SocketAddr,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Instant,
+};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::debug;
+
+use crate::handlers::{AppState, build_echo_response};
+
+/// A request recorded by the echo handler.
+#[derive(Debug, Serialize)]
+pub struct HistoryEntry {
+    pub id: u64,
+    pub timestamp: DateTime<Utc>,
+    pub status: u16,
+    pub duration_ms: u64,
+    pub request: Value,
+    #[serde(skip)]
+    size: usize,
+}
+
+impl HistoryEntry {
+    fn path(&self) -> Option<&str> {
+        self.request.get("path").and_then(Value::as_str)
+    }
+
+    fn method(&self) -> Option<&str> {
+        self.request.get("method").and_then(Value::as_str)
+    }
+}
+
+/// Number of entries buffered per live subscriber before it starts missing requests.
+const SUBSCRIBER_BUFFER: usize = 256;
+
+/// Ring buffer of the most recent echoed requests, bounded by entry count and by the total
+/// size of the serialized requests. Every recorded request is also broadcast to live
+/// subscribers of `/__stream`.
+#[derive(Debug)]
+pub struct History {
+    entries: Mutex<Entries>,
+    capacity: usize,
+    max_bytes: usize,
+    next_id: AtomicU64,
+    events: broadcast::Sender<Arc<HistoryEntry>>,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    items: VecDeque<Arc<HistoryEntry>>,
+    bytes: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize, max_bytes: usize) -> Self {
+        History {
+            entries: Mutex::new(Entries::default()),
+            capacity,
+            max_bytes,
+            next_id: AtomicU64::new(1),
+            events: broadcast::channel(SUBSCRIBER_BUFFER).0,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0 && self.max_bytes > 0
+    }
+
+    /// Whether recorded requests would be kept or delivered anywhere.
+    pub fn is_observed(&self) -> bool {
+        self.is_enabled() || self.events.receiver_count() > 0
+    }
+
+    /// Subscribes to requests recorded from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<HistoryEntry>> {
+        self.events.subscribe()
+    }
+
+    /// Records a request, evicting the oldest entries to stay within bounds. Requests larger
+    /// than the whole byte budget are not kept.
+    pub fn record(&self, status: u16, duration_ms: u64, request: Value) -> Arc<HistoryEntry> {
+        let size = request.to_string().len();
+        let entry = Arc::new(HistoryEntry {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            timestamp: Utc::now(),
+            status,
+            duration_ms,
+            request,
+            size,
+        });
+
+        // Sending only fails when nobody is listening
+        let _ = self.events.send(entry.clone());
+
+        if !self.is_enabled() || size > self.max_bytes {
+            return entry;
+        }
+
+        let mut entries = self.lock();
+        entries.bytes += size;
+        entries.items.push_back(entry.clone());
+
+        while entries.items.len() > self.capacity || entries.bytes > self.max_bytes {
+            match entries.items.pop_front() {
+                Some(evicted) => entries.bytes -= evicted.size,
+                None => break,
+            }
+        }
+
+        entry
+    }
+
+    pub fn get(&self, id: u64) -> Option<Arc<HistoryEntry>> {
+        self.lock().items.iter().find(|entry| entry.id == id).cloned()
+    }
+
+    /// Returns matching entries, oldest first. With a limit, only the newest are kept.
+    pub fn query(&self, filter: &HistoryFilter) -> Vec<Arc<HistoryEntry>> {
+        let entries = self.lock();
+        let mut matches: Vec<_> =
+            entries.items.iter().filter(|entry| filter.matches(entry)).cloned().collect();
+
+        if let Some(limit) = filter.limit {
+            let skip = matches.len().saturating_sub(limit);
+            matches.drain(..skip);
+        }
+
+        matches
+    }
+
+    pub fn clear(&self) {
+        let mut entries = self.lock();
+        entries.items.clear();
+        entries.bytes = 0;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Entries> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Query parameters accepted by `GET /__history` and `GET /__stream`.
+#[derive(Debug, Default, Deserialize)]
+pub struct HistoryFilter {
+    /// Exact path match
+    pub path: Option<String>,
+    /// Path prefix match
+    pub path_prefix: Option<String>,
+    /// HTTP method, case-insensitive
+    pub method: Option<String>,
+    /// Only entries recorded at or after this RFC 3339 time
+    pub since: Option<DateTime<Utc>>,
+    /// Only entries recorded at or before this RFC 3339 time
+    pub until: Option<DateTime<Utc>>,
+    /// Maximum number of entries, newest kept
+    pub limit: Option<usize>,
+}
+
+impl HistoryFilter {
+    pub fn matches(&self, entry: &HistoryEntry) -> bool {
+        self.path.as_deref().is_none_or(|path| entry.path() == Some(path))
+            && self
+                .path_prefix
+                .as_deref()
+                .is_none_or(|prefix| entry.path().is_some_and(|p| p.starts_with(prefix)))
+            && self
+                .method
+                .as_deref()
+                .is_none_or(|method| entry.method().is_some_and(|m| m.eq_ignore_ascii_case(method)))
+            && self.since.is_none_or(|since| entry.timestamp >= since)
+            && self.until.is_none_or(|until| entry.timestamp <= until)
+    }
+}
+
+/// Response extension carrying the echo handler's view of the request, for the history.
+#[derive(Debug, Clone)]
+pub struct RecordedRequest(pub Value);
+
+/// Middleware that records every echoed request in the history.
+pub async fn record_request(
+    State(state): State<AppState>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !state.history.is_observed() {
+        return next.run(request).await;
+    }
+
+    let start = Instant::now();
+    let (parts, body) = request.into_parts();
+    let head = parts.clone();
+
+    let mut response = next.run(Request::from_parts(parts, body)).await;
+
+    let request = match response.extensions_mut().remove::<RecordedRequest>() {
+        Some(RecordedRequest(request)) => request,
+        // Injected faults answer before the handler runs, leaving the body unread
+        None => {
+            let echo_response = build_echo_response(&state, addr, &head, Body::empty()).await;
+            serde_json::to_value(&echo_response).unwrap_or(Value::Null)
+        },
+    };
+    state.history.record(response.status().as_u16(), start.elapsed().as_millis() as u64, request);
+
+    response
+}
+
+pub async fn list_history(
+    State(state): State<AppState>,
+    Query(filter): Query<HistoryFilter>,
+) -> Json<Vec<Arc<HistoryEntry>>> {
+    Json(state.history.query(&filter))
+}
+
+pub async fn get_history_entry(State(state): State<AppState>, Path(id): Path<u64>) -> Response {
+    match state.history.get(id) {
+        Some(entry) => Json(entry).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+pub async fn clear_history(State(state): State<AppState>) -> StatusCode {
+    state.history.clear();
+    StatusCode::NO_CONTENT
+}
+
+/// Streams recorded requests as Server-Sent Events, optionally filtered by `path`,
+/// `path_prefix` and `method`.
+pub async fn stream_history(
+    State(state): State<AppState>,
+    Query(filter): Query<HistoryFilter>,
+) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    let receiver = state.history.subscribe();
+
+    let entries = stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(entry) => return Some((entry, receiver)),
+                Err(RecvError::Lagged(skipped)) => {
+                    debug!("History stream subscriber lagged by {} entries", skipped);
+                },
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    let events = entries
+        .filter(move |entry| ready(filter.matches(entry)))
+        .map(|entry| Event::default().event("request").id(entry.id.to_string()).json_data(&*entry));
+
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
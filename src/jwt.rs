@@ -0,0 +1,265 @@
+use axum::{
+    Json,
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use jsonwebtoken::{DecodingKey, Validation, errors::ErrorKind, jwk::JwkSet};
+use serde::Serialize;
+use serde_json::Value;
+use std::fs;
+
+use crate::{config::Config, error::Result, oidc::MockIssuer};
+
+/// Decoded `Authorization` token, with the verification outcome when verification is
+/// configured.
+#[derive(Debug, Serialize)]
+pub struct JwtInfo {
+    pub header: Option<Value>,
+    pub payload: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verified: Option<bool>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<String>,
+    /// ID of the key whose signature matched
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kid: Option<String>,
+}
+
+struct VerificationKey {
+    kid: Option<String>,
+    key: DecodingKey,
+}
+
+/// Checks token signatures against `--jwt-secret`, `--jwt-public-key` and `--jwt-jwks-file`,
+/// and the `iss`, `aud`, `exp` and `nbf` claims.
+pub struct JwtVerifier {
+    keys: Vec<VerificationKey>,
+    issuer: Option<String>,
+    audience: Vec<String>,
+    leeway: u64,
+    reject_invalid: bool,
+}
+
+/// Outcome of [`JwtVerifier::verify`]. The echo handler stores it in the request extensions
+/// so a token is verified once per request.
+#[derive(Debug, Default, Clone)]
+pub struct Verification {
+    pub errors: Vec<String>,
+    pub kid: Option<String>,
+}
+
+impl Verification {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl JwtVerifier {
+    /// Loads the configured keys, trusting the mock issuer's key too when it is enabled.
+    /// Returns `None` when there are no keys.
+    pub fn from_config(config: &Config, issuer: Option<&MockIssuer>) -> Result<Option<Self>> {
+        let mut keys = Vec::new();
+
+        if let Some(issuer) = issuer {
+            for jwk in &issuer.jwks().keys {
+                keys.push(VerificationKey {
+                    kid: jwk.common.key_id.clone(),
+                    key: DecodingKey::from_jwk(jwk)?,
+                });
+            }
+        }
+
+        if let Some(secret) = &config.jwt_secret {
+            keys.push(VerificationKey {
+                kid: None,
+                key: DecodingKey::from_secret(secret.as_bytes()),
+            });
+        }
+
+        if let Some(path) = &config.jwt_public_key {
+            let pem = fs::read(path)?;
+            let key = DecodingKey::from_rsa_pem(&pem)
+                .or_else(|_| DecodingKey::from_ec_pem(&pem))
+                .or_else(|_| DecodingKey::from_ed_pem(&pem))?;
+            keys.push(VerificationKey { kid: None, key });
+        }
+
+        if let Some(path) = &config.jwt_jwks_file {
+            let jwks: JwkSet = serde_json::from_slice(&fs::read(path)?)
+                .map_err(jsonwebtoken::errors::Error::from)?;
+            for jwk in &jwks.keys {
+                keys.push(VerificationKey {
+                    kid: jwk.common.key_id.clone(),
+                    key: DecodingKey::from_jwk(jwk)?,
+                });
+            }
+        }
+
+        if keys.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(JwtVerifier {
+            keys,
+            issuer: config.jwt_issuer.clone(),
+            audience: config.jwt_audience.clone(),
+            leeway: config.jwt_leeway_secs,
+            reject_invalid: config.jwt_reject_invalid,
+        }))
+    }
+
+    /// Verifies the signature, trying the key named by the token's `kid` first, then checks
+    /// every claim so all problems are reported at once.
+    pub fn verify(&self, token: &str) -> Verification {
+        let header = match jsonwebtoken::decode_header(token) {
+            Ok(header) => header,
+            Err(e) => {
+                return Verification { errors: vec![format!("malformed token: {}", e)], kid: None };
+            },
+        };
+
+        let mut validation = Validation::new(header.alg);
+        validation.required_spec_claims.clear();
+        validation.validate_exp = false;
+        validation.validate_aud = false;
+
+        let by_kid: Vec<&VerificationKey> =
+            self.keys.iter().filter(|key| key.kid.is_some() && key.kid == header.kid).collect();
+        let candidates = if by_kid.is_empty() { self.keys.iter().collect() } else { by_kid };
+
+        let mut signature_error = ErrorKind::InvalidAlgorithm;
+        let mut verified = None;
+        for candidate in candidates {
+            match jsonwebtoken::decode::<Value>(token, &candidate.key, &validation) {
+                Ok(data) => {
+                    verified = Some((candidate, data.claims));
+                    break;
+                },
+                // A key of another family says nothing about the signature itself
+                Err(e) if *e.kind() == ErrorKind::InvalidAlgorithm => {},
+                Err(e) => signature_error = e.into_kind(),
+            }
+        }
+
+        let Some((key, claims)) = verified else {
+            let error = match signature_error {
+                ErrorKind::InvalidAlgorithm => format!("no key for algorithm {:?}", header.alg),
+                ErrorKind::InvalidSignature => "invalid signature".to_string(),
+                other => format!("{:?}", other),
+            };
+            return Verification { errors: vec![error], kid: None };
+        };
+
+        Verification { errors: self.check_claims(&claims), kid: key.kid.clone() }
+    }
+
+    fn check_claims(&self, claims: &Value) -> Vec<String> {
+        let mut errors = Vec::new();
+        // NumericDate values may have a fractional part, so compare as floats
+        let now = jsonwebtoken::get_current_timestamp() as f64;
+        let leeway = self.leeway as f64;
+
+        match claims.get("exp").map(|exp| (exp, exp.as_f64())) {
+            Some((exp, Some(seconds))) if seconds + leeway < now => {
+                errors.push(format!("expired at {}", exp));
+            },
+            Some((_, None)) => errors.push("exp is not a number".to_string()),
+            _ => {},
+        }
+
+        match claims.get("nbf").map(|nbf| (nbf, nbf.as_f64())) {
+            Some((nbf, Some(seconds))) if seconds > now + leeway => {
+                errors.push(format!("not valid before {}", nbf));
+            },
+            Some((_, None)) => errors.push("nbf is not a number".to_string()),
+            _ => {},
+        }
+
+        if let Some(issuer) = &self.issuer {
+            match claims.get("iss").and_then(Value::as_str) {
+                Some(iss) if iss == issuer => {},
+                Some(iss) => errors.push(format!("issuer {} is not {}", iss, issuer)),
+                None => errors.push("missing iss".to_string()),
+            }
+        }
+
+        if !self.audience.is_empty() {
+            let audience: Vec<&str> = match claims.get("aud") {
+                Some(Value::String(aud)) => vec![aud.as_str()],
+                Some(Value::Array(auds)) => auds.iter().filter_map(Value::as_str).collect(),
+                _ => Vec::new(),
+            };
+            if audience.is_empty() {
+                errors.push("missing aud".to_string());
+            } else if !audience.iter().any(|aud| self.audience.iter().any(|a| a == aud)) {
+                errors.push(format!("audience {} is not accepted", audience.join(", ")));
+            }
+        }
+
+        errors
+    }
+}
+
+/// The token in `--jwt-header` (default `authorization`), without a `Bearer ` prefix.
+pub fn bearer_token<'a>(headers: &'a HeaderMap, config: &Config) -> Option<&'a str> {
+    let jwt_header = config.jwt_header.as_deref().unwrap_or("authorization");
+
+    headers
+        .get(jwt_header)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| if v.to_lowercase().starts_with("bearer ") { &v[7..] } else { v })
+}
+
+/// Verifies the request's token, if there is one and verification is configured.
+pub fn verify_request(
+    headers: &HeaderMap,
+    config: &Config,
+    verifier: Option<&JwtVerifier>,
+) -> Option<Verification> {
+    Some(verifier?.verify(bearer_token(headers, config)?))
+}
+
+pub fn extract_jwt(
+    headers: &HeaderMap,
+    config: &Config,
+    verification: Option<Verification>,
+) -> Option<JwtInfo> {
+    let token = bearer_token(headers, config)?;
+    let header = jsonwebtoken::decode_header(token).ok()?;
+
+    // Decode without verification (just for inspection)
+    let payload = if let Ok(token_data) = jsonwebtoken::dangerous::insecure_decode::<Value>(token) {
+        Some(token_data.claims)
+    } else {
+        None
+    };
+
+    Some(JwtInfo {
+        header: serde_json::to_value(header).ok(),
+        payload,
+        verified: verification.as_ref().map(Verification::is_valid),
+        kid: verification.as_ref().and_then(|v| v.kid.clone()),
+        errors: verification.map(|v| v.errors).unwrap_or_default(),
+    })
+}
+
+/// With `--jwt-reject-invalid`, answers requests whose token failed verification with 401.
+/// Requests without a token are let through.
+pub fn reject_invalid(
+    verification: Option<&Verification>,
+    verifier: Option<&JwtVerifier>,
+) -> Option<Response> {
+    verifier.filter(|verifier| verifier.reject_invalid)?;
+    let verification = verification.filter(|verification| !verification.is_valid())?;
+
+    let mut response = (
+        StatusCode::UNAUTHORIZED,
+        Json(serde_json::json!({ "error": "invalid_token", "errors": verification.errors })),
+    )
+        .into_response();
+    response.headers_mut().insert(
+        header::WWW_AUTHENTICATE,
+        HeaderValue::from_static("Bearer error=\"invalid_token\""),
+    );
+    Some(response)
+}
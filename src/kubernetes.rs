@@ -0,0 +1,90 @@
+use serde::Serialize;
+use std::{collections::BTreeMap, env, fs, path::Path};
+
+/// Namespace file mounted with the pod's service account token.
+const SERVICE_ACCOUNT_NAMESPACE: &str = "/var/run/secrets/kubernetes.io/serviceaccount/namespace";
+
+/// Identity of the pod serving the request, from the Downward API.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct PodInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pod_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pod_ip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service_account: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub annotations: BTreeMap<String, String>,
+}
+
+impl PodInfo {
+    /// Reads `POD_NAME`, `POD_NAMESPACE`, `NODE_NAME`, `POD_IP` and `POD_SERVICE_ACCOUNT`,
+    /// falling back to the `name`, `namespace`, `nodename`, `podip` and `serviceaccount` files
+    /// of a Downward API volume at `dir`, whose `labels` and `annotations` files are read too.
+    /// Returns `None` when nothing is available, i.e. outside Kubernetes.
+    pub fn load(dir: &Path) -> Option<Self> {
+        let value = |var: &str, file: &str| {
+            env::var(var)
+                .ok()
+                .or_else(|| fs::read_to_string(dir.join(file)).ok())
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+
+        let info = PodInfo {
+            pod_name: value("POD_NAME", "name"),
+            namespace: value("POD_NAMESPACE", "namespace").or_else(|| {
+                fs::read_to_string(SERVICE_ACCOUNT_NAMESPACE).ok().map(|ns| ns.trim().to_string())
+            }),
+            node_name: value("NODE_NAME", "nodename"),
+            pod_ip: value("POD_IP", "podip"),
+            service_account: value("POD_SERVICE_ACCOUNT", "serviceaccount"),
+            labels: read_map(&dir.join("labels")),
+            annotations: read_map(&dir.join("annotations")),
+        };
+
+        (info != PodInfo::default()).then_some(info)
+    }
+}
+
+/// Parses a Downward API map file: one `key="value"` per line, values quoted Go-style.
+fn read_map(path: &Path) -> BTreeMap<String, String> {
+    let Ok(contents) = fs::read_to_string(path) else {
+        return BTreeMap::new();
+    };
+
+    contents
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.trim().to_string(), unquote(value.trim())))
+        .collect()
+}
+
+fn unquote(value: &str) -> String {
+    let Some(inner) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) else {
+        return value.to_string();
+    };
+
+    let mut result = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('r') => result.push('\r'),
+            Some(other) => result.push(other),
+            None => result.push('\\'),
+        }
+    }
+    result
+}
@@ -0,0 +1,261 @@
+use axum::{
+    body::{Body, BodyDataStream},
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use bytes::Bytes;
+use futures_util::{StreamExt, stream};
+use rand::Rng;
+use std::{str::FromStr, time::Duration};
+use tokio::time::sleep;
+
+use crate::{
+    body::exact_len,
+    connection::SkipCompression,
+    handlers::{AppState, request_control},
+};
+
+/// z-score of the 99th percentile of a standard normal distribution.
+const Z_P99: f64 = 2.326_347_874;
+
+/// How long to delay a response, parsed from `x-set-response-delay-ms`.
+///
+/// - `300` - fixed delay
+/// - `100-500` - uniformly distributed between the bounds
+/// - `normal:200,50` - normally distributed with the given mean and standard deviation
+/// - `p50:100,p99:800` - log-normal distribution shaped by its median and 99th percentile
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DelaySpec {
+    Fixed(u64),
+    Range(u64, u64),
+    Normal { mean: f64, stddev: f64 },
+    Percentiles { p50: f64, p99: f64 },
+}
+
+impl FromStr for DelaySpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let invalid = || format!("Invalid delay: {}", s);
+
+        if let Some(params) = s.strip_prefix("normal:") {
+            let (mean, stddev) = params.split_once(',').ok_or_else(invalid)?;
+            let mean: f64 = mean.trim().parse().map_err(|_| invalid())?;
+            let stddev: f64 = stddev.trim().parse().map_err(|_| invalid())?;
+            if !mean.is_finite() || !stddev.is_finite() {
+                return Err(invalid());
+            }
+            return Ok(DelaySpec::Normal { mean, stddev });
+        }
+
+        if s.starts_with("p50:") {
+            let (p50, p99) = s.split_once(',').ok_or_else(invalid)?;
+            let p50: f64 = p50.trim_start_matches("p50:").trim().parse().map_err(|_| invalid())?;
+            let p99: f64 = p99
+                .trim()
+                .strip_prefix("p99:")
+                .ok_or_else(invalid)?
+                .parse()
+                .map_err(|_| invalid())?;
+            if !p50.is_finite() || !p99.is_finite() || p50 <= 0.0 || p99 < p50 {
+                return Err(invalid());
+            }
+            return Ok(DelaySpec::Percentiles { p50, p99 });
+        }
+
+        if let Some((min, max)) = s.split_once('-') {
+            let min: u64 = min.trim().parse().map_err(|_| invalid())?;
+            let max: u64 = max.trim().parse().map_err(|_| invalid())?;
+            if min > max {
+                return Err(invalid());
+            }
+            return Ok(DelaySpec::Range(min, max));
+        }
+
+        s.parse().map(DelaySpec::Fixed).map_err(|_| invalid())
+    }
+}
+
+impl DelaySpec {
+    /// Draws a delay from the distribution. Negative samples are clamped to zero and samples
+    /// too large for a `Duration` to `Duration::MAX`.
+    pub fn sample(&self, rng: &mut impl Rng) -> Duration {
+        let millis = match *self {
+            DelaySpec::Fixed(ms) => ms as f64,
+            DelaySpec::Range(min, max) => rng.random_range(min..=max) as f64,
+            DelaySpec::Normal { mean, stddev } => mean + stddev * standard_normal(rng),
+            DelaySpec::Percentiles { p50, p99 } => {
+                let sigma = (p99 / p50).ln() / Z_P99;
+                p50 * (sigma * standard_normal(rng)).exp()
+            },
+        };
+
+        Duration::try_from_secs_f64(millis.max(0.0) / 1000.0).unwrap_or(Duration::MAX)
+    }
+}
+
+/// Box-Muller transform.
+fn standard_normal(rng: &mut impl Rng) -> f64 {
+    let u1: f64 = 1.0 - rng.random::<f64>();
+    let u2: f64 = rng.random();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+/// Where in the response the delay is spent, from `x-set-response-delay-at`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DelayPlacement {
+    /// Wait before sending anything (time to first byte)
+    #[default]
+    Headers,
+    /// Send the headers, then wait before sending the body
+    Body,
+    /// Spread the delay evenly between body chunks
+    Chunks,
+}
+
+impl FromStr for DelayPlacement {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "headers" => Ok(DelayPlacement::Headers),
+            "body" => Ok(DelayPlacement::Body),
+            "chunks" => Ok(DelayPlacement::Chunks),
+            _ => Err(format!("Invalid delay placement: {}. Use 'headers', 'body' or 'chunks'", s)),
+        }
+    }
+}
+
+/// Middleware that applies the `x-set-response-delay-ms` delay at the requested point of the
+/// response.
+///
+/// `x-set-response-delay-at` chooses the placement and `x-set-response-delay-chunks` the
+/// number of body chunks used with `chunks` placement (default 10).
+pub async fn inject_latency(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let (parts, body) = request.into_parts();
+
+    let spec = match control::<DelaySpec>(&parts, "x-set-response-delay-ms") {
+        Ok(Some(spec)) => spec,
+        Ok(None) => return next.run(Request::from_parts(parts, body)).await,
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+
+    let placement = match control::<DelayPlacement>(&parts, "x-set-response-delay-at") {
+        Ok(placement) => placement.unwrap_or_default(),
+        Err(e) => return (StatusCode::BAD_REQUEST, e).into_response(),
+    };
+
+    let chunks = request_control::<usize>(&parts, "x-set-response-delay-chunks").unwrap_or(10);
+    let delay = state.faults.with_rng(|rng| spec.sample(rng));
+    #[cfg(feature = "prometheus")]
+    crate::metrics::record_fault("delay");
+
+    if placement == DelayPlacement::Headers {
+        sleep(delay).await;
+        return next.run(Request::from_parts(parts, body)).await;
+    }
+
+    let response = next.run(Request::from_parts(parts, body)).await;
+    let (mut parts, body) = response.into_parts();
+    let len = exact_len(&parts.headers, &body);
+    parts.headers.remove("content-length");
+    // Compression would buffer the paced pieces and move the pauses
+    parts.extensions.insert(SkipCompression);
+
+    let chunks = if placement == DelayPlacement::Body { 1 } else { chunks.max(1) };
+    Response::from_parts(parts, paced_body(body, len, chunks, delay))
+}
+
+struct Pacer {
+    inner: BodyDataStream,
+    pending: Bytes,
+    sent: u64,
+    chunk_size: Option<u64>,
+    pause: Duration,
+    pauses_left: u32,
+    finished: bool,
+}
+
+/// Splits `body` into up to `chunks` pieces and spreads `delay` evenly before each one. Bodies
+/// of known length `len` are cut into equal pieces; otherwise each frame of the inner body is
+/// a piece.
+/// Pauses left over when the body runs out of pieces are spent before it ends. Frames are
+/// forwarded as they arrive, so large generated bodies are never buffered.
+pub fn paced_body(body: Body, len: Option<u64>, chunks: usize, delay: Duration) -> Body {
+    let chunks = u32::try_from(chunks).unwrap_or(u32::MAX).max(1);
+    let chunk_size = len.map(|len| len.div_ceil(chunks as u64).max(1));
+
+    let state = Pacer {
+        inner: body.into_data_stream(),
+        pending: Bytes::new(),
+        sent: 0,
+        chunk_size,
+        pause: delay / chunks,
+        pauses_left: chunks,
+        finished: false,
+    };
+
+    Body::from_stream(
+        stream::unfold(state, |mut state| async move {
+            let mut new_frame = false;
+            while state.pending.is_empty() {
+                if state.finished {
+                    return None;
+                }
+                match state.inner.next().await {
+                    Some(Ok(bytes)) => {
+                        state.pending = bytes;
+                        new_frame = true;
+                    },
+                    Some(Err(e)) => {
+                        state.finished = true;
+                        return Some((Err(e), state));
+                    },
+                    None => {
+                        state.finished = true;
+                        sleep(state.pause.saturating_mul(state.pauses_left)).await;
+                        return None;
+                    },
+                }
+            }
+
+            // Never let a piece cross a chunk boundary
+            let (at_boundary, len) = match state.chunk_size {
+                Some(size) => {
+                    let into_chunk = state.sent % size;
+                    let left_in_chunk = usize::try_from(size - into_chunk).unwrap_or(usize::MAX);
+                    (into_chunk == 0, state.pending.len().min(left_in_chunk))
+                },
+                None => (new_frame, state.pending.len()),
+            };
+
+            if at_boundary && state.pauses_left > 0 {
+                sleep(state.pause).await;
+                state.pauses_left -= 1;
+            }
+
+            let piece = state.pending.split_to(len);
+            state.sent += len as u64;
+            Some((Ok(piece), state))
+        })
+        .fuse(),
+    )
+}
+
+/// Like [`request_control`], but surfaces values that fail to parse.
+fn control<T: FromStr<Err = String>>(
+    parts: &axum::http::request::Parts,
+    name: &str,
+) -> Result<Option<T>, String> {
+    match request_control::<String>(parts, name) {
+        Some(value) => value.parse().map(Some),
+        None => Ok(None),
+    }
+}
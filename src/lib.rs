@@ -1,11 +1,42 @@
+pub mod access_log;
+pub mod admission;
+pub mod body;
+#[cfg(feature = "mtls")]
+pub mod client_cert;
 pub mod config;
+pub mod connection;
 pub mod error;
+pub mod faults;
 pub mod handlers;
+pub mod health;
+pub mod history;
+#[cfg(feature = "jwt")]
+pub mod jwt;
+pub mod kubernetes;
+pub mod latency;
+pub mod load;
+#[cfg(feature = "prometheus")]
+pub mod metrics;
+#[cfg(feature = "jwt")]
+pub mod oidc;
+pub mod payload;
+pub mod resources;
+pub mod rules;
+pub mod streaming;
+pub mod template;
+pub mod tls;
 
-use axum::{Router, routing::get};
+use axum::{
+    Router,
+    handler::Handler,
+    middleware,
+    routing::{delete, get, post},
+};
 use tower::ServiceBuilder;
 use tower_http::{
-    compression::CompressionLayer, cors::CorsLayer, limit::RequestBodyLimitLayer, trace::TraceLayer,
+    compression::{CompressionLayer, DefaultPredicate, Predicate},
+    cors::CorsLayer,
+    limit::RequestBodyLimitLayer,
 };
 
 use handlers::{AppState, echo_handler, liveness_handler, readiness_handler};
@@ -14,7 +45,64 @@ pub fn build_router(state: AppState) -> Router {
     let mut router = Router::new()
         .route("/livez", get(liveness_handler))
         .route("/readyz", get(readiness_handler))
-        .fallback(echo_handler);
+        .fallback(
+            echo_handler
+                .layer(middleware::from_fn_with_state(state.clone(), latency::inject_latency))
+                .layer(middleware::from_fn(streaming::stream_response))
+                .layer(middleware::from_fn_with_state(state.clone(), faults::inject_faults))
+                .layer(middleware::from_fn(connection::inject_connection_fault))
+                .layer(middleware::from_fn_with_state(state.clone(), history::record_request)),
+        );
+
+    router = router
+        .route("/__health", get(health::get_health))
+        .route("/__resources", get(resources::resources_handler));
+
+    // Add probe control endpoints if enabled
+    if state.config.enable_probe_control {
+        router = router
+            .route("/__health/{probe}", post(health::update_probe).delete(health::reset_probe));
+    }
+
+    // Add admission webhook endpoint if enabled; without rules it allows every review
+    if state.config.enable_admission || state.config.admission_rules_file.is_some() {
+        router = router.route(
+            "/__admission",
+            post(admission::review).get(admission::list_reviews).delete(admission::clear_reviews),
+        );
+    }
+
+    // Add request history endpoints if enabled
+    if state.history.is_enabled() {
+        router = router
+            .route("/__history", get(history::list_history).delete(history::clear_history))
+            .route("/__history/{id}", get(history::get_history_entry));
+    }
+
+    // Add live tail endpoint if enabled; it works with or without a kept history
+    if state.config.enable_stream {
+        router = router.route("/__stream", get(history::stream_history));
+    }
+
+    // Add mock OIDC issuer endpoints if enabled
+    #[cfg(feature = "jwt")]
+    if state.oidc.is_some() {
+        router = router
+            .route("/.well-known/openid-configuration", get(oidc::discovery))
+            .route("/authorize", get(oidc::authorize).post(oidc::authorize))
+            .route("/jwks.json", get(oidc::jwks))
+            .route("/token", post(oidc::token));
+    }
+
+    // Add load generator endpoints if enabled
+    if state.config.enable_load {
+        router = router
+            .route("/__load", get(load::list_load).delete(load::cancel_all_load))
+            .route("/__load/cpu", post(load::start_cpu))
+            .route("/__load/memory", post(load::start_memory))
+            .route("/__load/ramp", post(load::start_ramp))
+            .route("/__load/{id}", delete(load::cancel_load));
+    }
 
     // Add Prometheus metrics endpoint if enabled
     #[cfg(feature = "prometheus")]
@@ -23,12 +111,26 @@ pub fn build_router(state: AppState) -> Router {
     }
 
     router = router.layer(
-        ServiceBuilder::new()
-            .layer(RequestBodyLimitLayer::new(state.config.max_body_size))
-            .layer(CompressionLayer::new())
-            .layer(TraceLayer::new_for_http()),
+        ServiceBuilder::new().layer(RequestBodyLimitLayer::new(state.config.max_body_size)).layer(
+            CompressionLayer::new()
+                .compress_when(DefaultPredicate::new().and(connection::allow_compression)),
+        ),
     );
 
+    router = router.layer(middleware::from_fn_with_state(state.clone(), connection::limit_headers));
+
+    // Log requests unless disabled
+    if !state.config.disable_request_logs {
+        router =
+            router.layer(middleware::from_fn_with_state(state.clone(), access_log::log_requests));
+    }
+
+    // Record request metrics if enabled
+    #[cfg(feature = "prometheus")]
+    if state.config.prometheus {
+        router = router.layer(middleware::from_fn(metrics::track_requests));
+    }
+
     // Add CORS if enabled
     if state.config.enable_cors {
         router = router.layer(CorsLayer::permissive());
@@ -0,0 +1,371 @@
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tokio::sync::Notify;
+use tracing::info;
+
+use crate::{handlers::AppState, payload::parse_size};
+
+/// Length of one busy/idle cycle of a CPU burner thread.
+const BURN_SLICE: Duration = Duration::from_millis(10);
+
+/// Memory is allocated in chunks of this size so a ramp can grow it step by step.
+const ALLOC_CHUNK: usize = 64 * 1024 * 1024;
+
+const PAGE_SIZE: usize = 4096;
+
+/// Most increments a ramp job may take.
+const MAX_RAMP_STEPS: u32 = 1000;
+
+/// A running load job, as reported by `GET /__load`.
+#[derive(Debug, Clone, Serialize)]
+pub struct LoadJob {
+    pub id: u64,
+    pub kind: &'static str,
+    /// Cores currently being burned
+    pub cpu_cores: f64,
+    /// Bytes currently allocated and held
+    pub memory_bytes: u64,
+    pub started_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+}
+
+struct Job {
+    info: LoadJob,
+    /// Cores and bytes the job reaches at its peak, counted against the caps while it runs
+    reserved: (f64, u64),
+    cancel: Arc<Notify>,
+}
+
+/// Parameters of `POST /__load/{cpu,memory,ramp}`.
+#[derive(Debug, Default, Deserialize)]
+pub struct LoadParams {
+    /// Cores to burn, fractions allowed
+    pub cores: Option<f64>,
+    /// Memory to allocate, e.g. `256MB`
+    pub memory: Option<String>,
+    /// How long to hold the load (cpu and memory jobs; ramps take `steps * step_secs`)
+    pub duration_secs: Option<u64>,
+    /// Number of equal increments to reach the target (ramp jobs)
+    pub steps: Option<u32>,
+    /// Seconds between increments; the final level is held for one more step (ramp jobs)
+    pub step_secs: Option<u64>,
+}
+
+/// Runs cancellable CPU and memory load. The caps bound the total over all running jobs.
+pub struct LoadGenerator {
+    max_cores: f64,
+    max_memory: Option<u64>,
+    max_duration: Duration,
+    next_id: AtomicU64,
+    jobs: Mutex<BTreeMap<u64, Job>>,
+}
+
+impl LoadGenerator {
+    pub fn new(max_cores: Option<f64>, max_memory: Option<u64>, max_duration: Duration) -> Self {
+        let available = thread::available_parallelism().map(|n| n.get()).unwrap_or(1) as f64;
+
+        LoadGenerator {
+            max_cores: max_cores.unwrap_or(available),
+            max_memory,
+            max_duration,
+            next_id: AtomicU64::new(1),
+            jobs: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn jobs(&self) -> Vec<LoadJob> {
+        self.lock().values().map(|job| job.info.clone()).collect()
+    }
+
+    /// Stops a job early. Returns whether it was running.
+    pub fn cancel(&self, id: u64) -> bool {
+        match self.lock().get(&id) {
+            Some(job) => {
+                job.cancel.notify_one();
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn cancel_all(&self) {
+        for job in self.lock().values() {
+            job.cancel.notify_one();
+        }
+    }
+
+    /// Validates the request and starts the job in the background. Invalid requests fail with
+    /// 400, and jobs that would take the running total over a cap with 409.
+    pub fn start(
+        self: &Arc<Self>,
+        kind: &'static str,
+        params: &LoadParams,
+    ) -> Result<LoadJob, (StatusCode, String)> {
+        let bad_request = |message: String| (StatusCode::BAD_REQUEST, message);
+
+        let cores = params.cores.unwrap_or(0.0);
+        if !cores.is_finite() || cores < 0.0 || cores > self.max_cores {
+            return Err(bad_request(format!("cores must be between 0 and {}", self.max_cores)));
+        }
+
+        let memory =
+            params.memory.as_deref().map(parse_size).transpose().map_err(bad_request)?.unwrap_or(0);
+        if let Some(max) = self.max_memory.filter(|&max| memory > max) {
+            return Err(bad_request(format!("memory must be at most {} bytes", max)));
+        }
+
+        let problem = match kind {
+            "cpu" if cores == 0.0 => Some("cores is required"),
+            "memory" if memory == 0 => Some("memory is required"),
+            "ramp" if cores == 0.0 && memory == 0 => Some("cores or memory is required"),
+            "ramp" if params.duration_secs.is_some() => {
+                Some("duration_secs does not apply to ramps, which last steps * step_secs")
+            },
+            "cpu" | "memory" if params.steps.is_some() || params.step_secs.is_some() => {
+                Some("steps and step_secs only apply to ramps")
+            },
+            _ => None,
+        };
+        if let Some(problem) = problem {
+            return Err(bad_request(problem.to_string()));
+        }
+
+        let (steps, step) = match kind {
+            "ramp" => (
+                params.steps.unwrap_or(5).max(1),
+                Duration::from_secs(params.step_secs.unwrap_or(10)),
+            ),
+            _ => (1, Duration::from_secs(params.duration_secs.unwrap_or(60))),
+        };
+
+        if steps > MAX_RAMP_STEPS {
+            return Err(bad_request(format!("steps must be at most {}", MAX_RAMP_STEPS)));
+        }
+
+        let total = step
+            .checked_mul(steps)
+            .filter(|total| *total <= self.max_duration)
+            .ok_or_else(|| {
+                bad_request(format!("duration must be at most {}s", self.max_duration.as_secs()))
+            })?;
+
+        let levels = (1..=steps)
+            .map(|i| {
+                let fraction = i as f64 / steps as f64;
+                (cores * fraction, (memory as f64 * fraction) as u64)
+            })
+            .collect::<Vec<_>>();
+
+        // Check the caps and register the job under one lock, so concurrent requests can't
+        // overshoot them together
+        let mut jobs = self.lock();
+        let reserved_cores: f64 = jobs.values().map(|job| job.reserved.0).sum();
+        let reserved_memory: u64 = jobs.values().map(|job| job.reserved.1).sum();
+        if reserved_cores + cores > self.max_cores {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("running jobs already use {} of {} cores", reserved_cores, self.max_cores),
+            ));
+        }
+        if let Some(max) = self.max_memory.filter(|&max| reserved_memory + memory > max) {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("running jobs already use {} of {} bytes", reserved_memory, max),
+            ));
+        }
+
+        let now = Utc::now();
+        let info = LoadJob {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            kind,
+            cpu_cores: 0.0,
+            memory_bytes: 0,
+            started_at: now,
+            ends_at: now + total,
+        };
+        let cancel = Arc::new(Notify::new());
+        let job = Job { info: info.clone(), reserved: (cores, memory), cancel: cancel.clone() };
+        jobs.insert(info.id, job);
+        drop(jobs);
+
+        info!("Starting {} load job {}: {} cores, {} bytes", kind, info.id, cores, memory);
+        tokio::spawn(self.clone().run(info.id, levels, step, cancel));
+        Ok(info)
+    }
+
+    async fn run(
+        self: Arc<Self>,
+        id: u64,
+        levels: Vec<(f64, u64)>,
+        step: Duration,
+        cancel: Arc<Notify>,
+    ) {
+        let mut burner: Option<CpuBurner> = None;
+        let mut memory: Vec<Vec<u8>> = Vec::new();
+        let mut held = 0u64;
+
+        for (cores, bytes) in levels {
+            if let Some(burner) = burner.take() {
+                burner.stop();
+            }
+            if cores > 0.0 {
+                burner = Some(CpuBurner::start(cores));
+            }
+
+            if bytes > held {
+                let extra = (bytes - held) as usize;
+                let allocated =
+                    tokio::task::spawn_blocking(move || allocate(extra)).await.unwrap_or_default();
+                memory.extend(allocated);
+                held = bytes;
+            }
+
+            self.update(id, cores, held);
+
+            tokio::select! {
+                _ = tokio::time::sleep(step) => {},
+                _ = cancel.notified() => break,
+            }
+        }
+
+        if let Some(burner) = burner {
+            burner.stop();
+        }
+        drop(memory);
+
+        self.lock().remove(&id);
+        self.update(id, 0.0, 0);
+        info!("Load job {} finished", id);
+    }
+
+    fn update(&self, id: u64, cores: f64, memory: u64) {
+        let mut jobs = self.lock();
+        if let Some(job) = jobs.get_mut(&id) {
+            job.info.cpu_cores = cores;
+            job.info.memory_bytes = memory;
+        }
+
+        #[cfg(feature = "prometheus")]
+        crate::metrics::record_load(
+            jobs.values().map(|job| job.info.cpu_cores).sum(),
+            jobs.values().map(|job| job.info.memory_bytes).sum(),
+        );
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeMap<u64, Job>> {
+        self.jobs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Threads that keep `cores` worth of CPU busy. Fractional loads are spread evenly over
+/// `ceil(cores)` threads, each busy for its share of every time slice.
+struct CpuBurner {
+    stop: Arc<AtomicBool>,
+}
+
+impl CpuBurner {
+    fn start(cores: f64) -> Self {
+        let stop = Arc::new(AtomicBool::new(false));
+        let threads = cores.ceil() as usize;
+        let duty = cores / threads as f64;
+
+        for _ in 0..threads {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                let busy = BURN_SLICE.mul_f64(duty);
+                let mut x = 0u64;
+                while !stop.load(Ordering::Relaxed) {
+                    let start = Instant::now();
+                    while start.elapsed() < busy {
+                        x = std::hint::black_box(
+                            x.wrapping_mul(6364136223846793005).wrapping_add(1),
+                        );
+                    }
+                    if let Some(idle) = BURN_SLICE.checked_sub(start.elapsed()) {
+                        thread::sleep(idle);
+                    }
+                }
+            });
+        }
+
+        CpuBurner { stop }
+    }
+
+    fn stop(self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Allocates `bytes` and touches every page so the memory is actually resident.
+fn allocate(bytes: usize) -> Vec<Vec<u8>> {
+    let mut chunks = Vec::new();
+    let mut remaining = bytes;
+
+    while remaining > 0 {
+        let size = remaining.min(ALLOC_CHUNK);
+        let mut chunk = vec![0u8; size];
+        for page in chunk.iter_mut().step_by(PAGE_SIZE) {
+            *page = 1;
+        }
+        chunks.push(chunk);
+        remaining -= size;
+    }
+
+    chunks
+}
+
+pub async fn list_load(State(state): State<AppState>) -> Json<Vec<LoadJob>> {
+    Json(state.load.jobs())
+}
+
+pub async fn start_cpu(
+    State(state): State<AppState>,
+    Query(params): Query<LoadParams>,
+) -> Response {
+    start(&state, "cpu", LoadParams { memory: None, ..params })
+}
+
+pub async fn start_memory(
+    State(state): State<AppState>,
+    Query(params): Query<LoadParams>,
+) -> Response {
+    start(&state, "memory", LoadParams { cores: None, ..params })
+}
+
+pub async fn start_ramp(
+    State(state): State<AppState>,
+    Query(params): Query<LoadParams>,
+) -> Response {
+    start(&state, "ramp", params)
+}
+
+fn start(state: &AppState, kind: &'static str, params: LoadParams) -> Response {
+    match state.load.start(kind, &params) {
+        Ok(job) => (StatusCode::ACCEPTED, Json(job)).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+pub async fn cancel_load(State(state): State<AppState>, Path(id): Path<u64>) -> StatusCode {
+    if state.load.cancel(id) { StatusCode::NO_CONTENT } else { StatusCode::NOT_FOUND }
+}
+
+pub async fn cancel_all_load(State(state): State<AppState>) -> StatusCode {
+    state.load.cancel_all();
+    StatusCode::NO_CONTENT
+}
@@ -1,7 +1,13 @@
+mod tail;
+
 use anyhow::{Context, Result};
-use axum_server::tls_rustls::RustlsConfig;
+use axum::Extension;
+use axum_server::{
+    Handle,
+    tls_rustls::{RustlsAcceptor, RustlsConfig},
+};
 use clap::Parser;
-use std::{net::SocketAddr, path::Path, sync::Arc};
+use std::{net::SocketAddr, path::Path, sync::Arc, time::Duration};
 use tokio::signal;
 use tracing::{info, warn};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
@@ -22,8 +28,10 @@ fn health_check() -> Result<()> {
 
 use k8swalski::{
     build_router,
-    config::{Config, LogFormat},
+    config::{Command, Config, LogFormat},
+    connection::{FaultAcceptor, Listener, configure_http},
     handlers::AppState,
+    tls::{self, TlsInfoAcceptor},
 };
 
 #[tokio::main]
@@ -39,8 +47,13 @@ async fn main() -> Result<()> {
         return health_check();
     }
 
+    // Handle subcommands
+    if let Some(Command::Tail(args)) = &config.command {
+        return tail::run(args).await;
+    }
+
     // Initialize logging
-    init_logging(&config.log_format);
+    init_logging(&config);
 
     info!("Starting k8swalski echo server");
     info!("HTTP port: {}", config.http_port);
@@ -55,41 +68,78 @@ async fn main() -> Result<()> {
     info!("Hostname: {}", hostname);
 
     // Create application state
-    let state = AppState { config: Arc::new(config.clone()), hostname };
+    let state = AppState::new(config.clone(), hostname)
+        .context("Failed to initialize application state")?;
+
+    if !state.rules.is_empty() {
+        info!("Loaded {} mock rules", state.rules.len());
+    }
+
+    if !state.admission.is_empty() {
+        info!("Loaded {} admission rules", state.admission.len());
+    }
+
+    if let Some(pod) = &state.kubernetes {
+        info!(
+            "Running as pod {}/{}",
+            pod.namespace.as_deref().unwrap_or("-"),
+            pod.pod_name.as_deref().unwrap_or("-")
+        );
+    }
+
+    let access_log = state.access_log.clone();
+
+    // Drain both listeners on shutdown
+    let http_server = Handle::new();
+    let https_server = Handle::new();
+    tokio::spawn(drain_on_shutdown(state.clone(), vec![http_server.clone(), https_server.clone()]));
 
     // Spawn HTTP server
     let http_handle = {
         let state = state.clone();
         let port = config.http_port;
-        tokio::spawn(async move { run_http_server(port, state).await })
+        tokio::spawn(async move { run_http_server(port, state, http_server).await })
     };
 
     // Generate certificates if they don't exist
-    generate_certs_if_missing(&config.tls_cert_path, &config.tls_key_path).await?;
+    generate_certs_if_missing(&config.tls_cert_path, &config.tls_key_path, &config.tls_san).await?;
 
     // Spawn HTTPS server
     let https_handle = {
         let port = config.https_port;
         let cert_path = config.tls_cert_path.clone();
         let key_path = config.tls_key_path.clone();
-        tokio::spawn(async move { run_https_server(port, &cert_path, &key_path, state).await })
+        tokio::spawn(async move {
+            run_https_server(port, &cert_path, &key_path, state, https_server).await
+        })
     };
 
     // Wait for servers to complete (they handle shutdown internally)
     let (http_result, https_result) = tokio::join!(http_handle, https_handle);
 
-    if let Err(e) = http_result {
-        warn!("HTTP server task error: {}", e);
+    match http_result {
+        Ok(Err(e)) => warn!("HTTP server error: {:#}", e),
+        Err(e) => warn!("HTTP server task error: {}", e),
+        Ok(Ok(())) => {},
     }
-    if let Err(e) = https_result {
-        warn!("HTTPS server task error: {}", e);
+    match https_result {
+        Ok(Err(e)) => warn!("HTTPS server error: {:#}", e),
+        Err(e) => warn!("HTTPS server task error: {}", e),
+        Ok(Ok(())) => {},
     }
 
+    // Write out access-log entries still queued for the writer thread
+    access_log.flush();
+
     info!("Servers stopped");
     Ok(())
 }
 
-async fn generate_certs_if_missing(cert_path: &Path, key_path: &Path) -> Result<()> {
+async fn generate_certs_if_missing(
+    cert_path: &Path,
+    key_path: &Path,
+    extra_names: &[String],
+) -> Result<()> {
     // Check if certificates already exist
     if cert_path.exists() && key_path.exists() {
         info!("Using existing TLS certificates");
@@ -99,8 +149,11 @@ async fn generate_certs_if_missing(cert_path: &Path, key_path: &Path) -> Result<
     info!("Generating self-signed TLS certificates...");
 
     // Generate certificate with rcgen
-    let mut params = rcgen::CertificateParams::new(vec!["localhost".to_string()])
-        .context("Failed to create certificate params")?;
+    // IP addresses among the extra names become IP SANs
+    let mut names = vec!["localhost".to_string()];
+    names.extend(extra_names.iter().cloned());
+    let mut params =
+        rcgen::CertificateParams::new(names).context("Failed to create certificate params")?;
     params.distinguished_name = rcgen::DistinguishedName::new();
     params.distinguished_name.push(rcgen::DnType::CommonName, "localhost");
 
@@ -121,39 +174,45 @@ async fn generate_certs_if_missing(cert_path: &Path, key_path: &Path) -> Result<
     Ok(())
 }
 
-fn init_logging(format: &LogFormat) {
-    match format {
+fn init_logging(config: &Config) {
+    let filter = || {
+        tracing_subscriber::EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| "k8swalski=info".into())
+    };
+
+    match config.log_format {
         LogFormat::Json => {
             tracing_subscriber::registry()
-                .with(
-                    tracing_subscriber::EnvFilter::try_from_default_env()
-                        .unwrap_or_else(|_| "k8swalski=info,tower_http=info".into()),
-                )
+                .with(filter())
                 .with(tracing_subscriber::fmt::layer().json())
                 .init();
         },
+        LogFormat::Human if config.log_without_newline => {
+            tracing_subscriber::registry()
+                .with(filter())
+                .with(tracing_subscriber::fmt::layer().compact())
+                .init();
+        },
         LogFormat::Human => {
             tracing_subscriber::registry()
-                .with(
-                    tracing_subscriber::EnvFilter::try_from_default_env()
-                        .unwrap_or_else(|_| "k8swalski=info,tower_http=info".into()),
-                )
+                .with(filter())
                 .with(tracing_subscriber::fmt::layer().pretty())
                 .init();
         },
     }
 }
 
-async fn run_http_server(port: u16, state: AppState) -> Result<()> {
+async fn run_http_server(port: u16, state: AppState, handle: Handle<SocketAddr>) -> Result<()> {
     let addr = SocketAddr::from(([0, 0, 0, 0], port));
     info!("HTTP server listening on {}", addr);
 
-    let app = build_router(state);
-    let listener =
-        tokio::net::TcpListener::bind(addr).await.context("Failed to bind HTTP listener")?;
+    let app = build_router(state.clone()).layer(Extension(Listener::Http));
+
+    let mut server = axum_server::bind(addr).acceptor(FaultAcceptor).handle(handle);
+    configure_http(server.http_builder(), &state.config);
 
-    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
-        .with_graceful_shutdown(shutdown_signal())
+    server
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
         .await
         .context("HTTP server error")?;
 
@@ -166,16 +225,22 @@ async fn run_https_server(
     cert_path: &Path,
     key_path: &Path,
     state: AppState,
+    handle: Handle<SocketAddr>,
 ) -> Result<()> {
     let addr = SocketAddr::from(([0, 0, 0, 0], port));
     info!("HTTPS server listening on {}", addr);
 
-    let app = build_router(state);
-    let tls_config = RustlsConfig::from_pem_file(cert_path, key_path)
-        .await
+    let app = build_router(state.clone()).layer(Extension(Listener::Https));
+    let tls_config = tls::server_config(cert_path, key_path, &state.config)
         .context("Failed to load TLS configuration")?;
+    let tls_config = RustlsConfig::from_config(Arc::new(tls_config));
 
-    axum_server::bind_rustls(addr, tls_config)
+    let mut server = axum_server::bind(addr)
+        .acceptor(TlsInfoAcceptor::new(RustlsAcceptor::new(tls_config).acceptor(FaultAcceptor)))
+        .handle(handle);
+    configure_http(server.http_builder(), &state.config);
+
+    server
         .serve(app.into_make_service_with_connect_info::<SocketAddr>())
         .await
         .context("HTTPS server error")?;
@@ -184,6 +249,25 @@ async fn run_https_server(
     Ok(())
 }
 
+/// Waits for SIGTERM or Ctrl+C, fails readiness for the pre-stop delay, then stops accepting
+/// connections and gives in-flight requests the grace period to finish.
+async fn drain_on_shutdown(state: AppState, handles: Vec<Handle<SocketAddr>>) {
+    shutdown_signal().await;
+    state.health.begin_shutdown();
+
+    let pre_stop_delay = Duration::from_secs(state.config.pre_stop_delay_secs);
+    if !pre_stop_delay.is_zero() {
+        info!("Shutdown requested, reporting not ready for {:?}", pre_stop_delay);
+        tokio::time::sleep(pre_stop_delay).await;
+    }
+
+    let grace_period = Duration::from_secs(state.config.shutdown_grace_period_secs);
+    info!("Draining connections for up to {:?}", grace_period);
+    for handle in handles {
+        handle.graceful_shutdown(Some(grace_period));
+    }
+}
+
 async fn shutdown_signal() {
     let ctrl_c = async {
         signal::ctrl_c().await.expect("Failed to install Ctrl+C handler");
@@ -0,0 +1,165 @@
+use axum::{
+    extract::{MatchedPath, Request},
+    http::{HeaderMap, Method},
+    middleware::Next,
+    response::Response,
+};
+use prometheus::{
+    Gauge, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, IntGaugeVec, Opts,
+    exponential_buckets, register_gauge, register_histogram_vec, register_int_counter_vec,
+    register_int_gauge, register_int_gauge_vec,
+};
+use std::{sync::LazyLock, time::Instant};
+
+use crate::{body::CountingBody, connection::Listener, rules::MatchedRule};
+
+/// Latency buckets in seconds, stretched to cover injected delays.
+const LATENCY_BUCKETS: &[f64] =
+    &[0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+/// Request metrics, registered once in the default registry served by `/metrics`.
+pub struct Metrics {
+    requests: IntCounterVec,
+    duration: HistogramVec,
+    in_flight: IntGaugeVec,
+    request_size: HistogramVec,
+    response_size: HistogramVec,
+    faults: IntCounterVec,
+    load_cpu_cores: Gauge,
+    load_memory_bytes: IntGauge,
+}
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(|| {
+    let labels = &["method", "status_class", "listener", "route"];
+    let size_buckets = exponential_buckets(64.0, 4.0, 10).expect("valid size buckets");
+
+    Metrics {
+        requests: register_int_counter_vec!(
+            Opts::new("k8swalski_http_requests_total", "Total HTTP requests handled"),
+            labels
+        )
+        .expect("register requests counter"),
+        duration: register_histogram_vec!(
+            HistogramOpts::new(
+                "k8swalski_http_request_duration_seconds",
+                "Time until the response headers were ready"
+            )
+            .buckets(LATENCY_BUCKETS.to_vec()),
+            labels
+        )
+        .expect("register duration histogram"),
+        in_flight: register_int_gauge_vec!(
+            Opts::new("k8swalski_http_requests_in_flight", "Requests currently being handled"),
+            &["listener"]
+        )
+        .expect("register in-flight gauge"),
+        request_size: register_histogram_vec!(
+            HistogramOpts::new("k8swalski_http_request_size_bytes", "Request body sizes")
+                .buckets(size_buckets.clone()),
+            &["listener", "route"]
+        )
+        .expect("register request size histogram"),
+        response_size: register_histogram_vec!(
+            HistogramOpts::new("k8swalski_http_response_size_bytes", "Response body sizes")
+                .buckets(size_buckets),
+            &["listener", "route"]
+        )
+        .expect("register response size histogram"),
+        faults: register_int_counter_vec!(
+            Opts::new("k8swalski_injected_faults_total", "Faults injected into responses"),
+            &["kind"]
+        )
+        .expect("register faults counter"),
+        load_cpu_cores: register_gauge!(
+            "k8swalski_load_cpu_cores",
+            "CPU cores being burned by load jobs"
+        )
+        .expect("register load CPU gauge"),
+        load_memory_bytes: register_int_gauge!(
+            "k8swalski_load_memory_bytes",
+            "Memory held by load jobs"
+        )
+        .expect("register load memory gauge"),
+    }
+});
+
+/// Counts an injected fault of the given kind.
+pub fn record_fault(kind: &str) {
+    METRICS.faults.with_label_values(&[kind]).inc();
+}
+
+/// Sets the load currently generated by `/__load` jobs.
+pub fn record_load(cpu_cores: f64, memory_bytes: u64) {
+    METRICS.load_cpu_cores.set(cpu_cores);
+    METRICS.load_memory_bytes.set(memory_bytes as i64);
+}
+
+/// Middleware that records request counts, latency, in-flight requests and body sizes.
+///
+/// The route label is the matched path for fixed endpoints, `rule:<name>` for mock rules and
+/// `echo` for everything else. Unknown methods are reported as `OTHER`.
+pub async fn track_requests(request: Request, next: Next) -> Response {
+    let start = Instant::now();
+    let method = method_label(request.method());
+    let listener = request.extensions().get::<Listener>().copied().unwrap_or_default().as_str();
+    let matched_path = request.extensions().get::<MatchedPath>().map(|p| p.as_str().to_string());
+    let request_size = declared_size(request.headers());
+
+    let in_flight = InFlight::start(listener);
+    let response = next.run(request).await;
+    drop(in_flight);
+
+    let route = match (matched_path, response.extensions().get::<MatchedRule>()) {
+        (Some(path), _) => path,
+        (None, Some(rule)) => format!("rule:{}", rule.0),
+        (None, None) => "echo".to_string(),
+    };
+    let status_class = format!("{}xx", response.status().as_u16() / 100);
+    let labels = [method, status_class.as_str(), listener, route.as_str()];
+
+    METRICS.requests.with_label_values(&labels).inc();
+    METRICS.duration.with_label_values(&labels).observe(start.elapsed().as_secs_f64());
+    if let Some(size) = request_size {
+        METRICS.request_size.with_label_values(&[listener, route.as_str()]).observe(size as f64);
+    }
+
+    let histogram = METRICS.response_size.with_label_values(&[listener, route.as_str()]);
+    response.map(|body| CountingBody::wrap(body, move |bytes| histogram.observe(bytes as f64)))
+}
+
+/// Counts a request as in flight until dropped, so requests whose client goes away mid-way
+/// are released too.
+struct InFlight(IntGauge);
+
+impl InFlight {
+    fn start(listener: &str) -> Self {
+        let gauge = METRICS.in_flight.with_label_values(&[listener]);
+        gauge.inc();
+        InFlight(gauge)
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+fn method_label(method: &Method) -> &'static str {
+    match *method {
+        Method::GET => "GET",
+        Method::POST => "POST",
+        Method::PUT => "PUT",
+        Method::DELETE => "DELETE",
+        Method::PATCH => "PATCH",
+        Method::HEAD => "HEAD",
+        Method::OPTIONS => "OPTIONS",
+        Method::CONNECT => "CONNECT",
+        Method::TRACE => "TRACE",
+        _ => "OTHER",
+    }
+}
+
+fn declared_size(headers: &HeaderMap) -> Option<u64> {
+    headers.get("content-length").and_then(|v| v.to_str().ok()).and_then(|v| v.parse().ok())
+}
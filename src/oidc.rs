@@ -0,0 +1,241 @@
+use axum::{
+    Form, Json,
+    extract::{State, rejection::FormRejection},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use base64::{Engine, engine::general_purpose::STANDARD};
+use jsonwebtoken::{
+    Algorithm, EncodingKey, Header,
+    jwk::{Jwk, JwkSet, ThumbprintHash},
+};
+use serde::Deserialize;
+use serde_json::{Map, Value, json};
+use std::fs;
+
+use crate::{
+    config::Config,
+    error::{AppError, Result},
+    handlers::AppState,
+};
+
+/// Offline identity provider that signs tokens for any client, served when `--oidc-issuer` is
+/// set.
+pub struct MockIssuer {
+    issuer: String,
+    algorithm: Algorithm,
+    key: EncodingKey,
+    jwk: Jwk,
+    ttl_secs: u64,
+    audience: Option<String>,
+    claims: Map<String, Value>,
+}
+
+/// Parameters of `POST /token`. Client secrets and passwords are accepted without checking.
+#[derive(Debug, Default, Deserialize)]
+pub struct TokenRequest {
+    pub grant_type: String,
+    pub client_id: Option<String>,
+    pub username: Option<String>,
+    pub scope: Option<String>,
+    pub audience: Option<String>,
+    /// JSON object of extra claims for this token
+    pub claims: Option<String>,
+}
+
+impl MockIssuer {
+    /// Signs with `--oidc-signing-key` (RSA, EC P-256 or Ed25519 PEM) or a generated P-256
+    /// key. Returns `None` unless `--oidc-issuer` is set.
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        let Some(issuer) = &config.oidc_issuer else {
+            return Ok(None);
+        };
+
+        let pem = match &config.oidc_signing_key {
+            Some(path) => fs::read(path)?,
+            None => rcgen::KeyPair::generate()
+                .map_err(|e| AppError::Oidc(format!("cannot generate signing key: {}", e)))?
+                .serialize_pem()
+                .into_bytes(),
+        };
+        let (algorithm, key) = EncodingKey::from_rsa_pem(&pem)
+            .map(|key| (Algorithm::RS256, key))
+            .or_else(|_| EncodingKey::from_ec_pem(&pem).map(|key| (Algorithm::ES256, key)))
+            .or_else(|_| EncodingKey::from_ed_pem(&pem).map(|key| (Algorithm::EdDSA, key)))?;
+
+        let mut jwk = Jwk::from_encoding_key(&key, algorithm)?;
+        jwk.common.key_id = Some(jwk.thumbprint(ThumbprintHash::SHA256));
+
+        let claims = match &config.oidc_claims {
+            Some(claims) => parse_claims(claims).map_err(AppError::Oidc)?,
+            None => Map::new(),
+        };
+
+        Ok(Some(MockIssuer {
+            issuer: issuer.trim_end_matches('/').to_string(),
+            algorithm,
+            key,
+            jwk,
+            ttl_secs: config.oidc_token_ttl_secs,
+            audience: config.oidc_audience.clone(),
+            claims,
+        }))
+    }
+
+    pub fn jwks(&self) -> JwkSet {
+        JwkSet { keys: vec![self.jwk.clone()] }
+    }
+
+    /// Signs a token for `subject`, layering the configured claims and then `extra` over the
+    /// standard ones.
+    pub fn issue(
+        &self,
+        subject: &str,
+        audience: &str,
+        extra: &Map<String, Value>,
+    ) -> jsonwebtoken::errors::Result<String> {
+        let now = jsonwebtoken::get_current_timestamp();
+        let mut claims = Map::new();
+        claims.insert("iss".to_string(), json!(self.issuer));
+        claims.insert("sub".to_string(), json!(subject));
+        claims.insert("aud".to_string(), json!(audience));
+        claims.insert("iat".to_string(), json!(now));
+        claims.insert("nbf".to_string(), json!(now));
+        claims.insert("exp".to_string(), json!(now.saturating_add(self.ttl_secs)));
+        claims.insert("jti".to_string(), json!(uuid::Uuid::new_v4().to_string()));
+        claims.extend(self.claims.clone());
+        claims.extend(extra.clone());
+
+        let header = Header { kid: self.jwk.common.key_id.clone(), ..Header::new(self.algorithm) };
+        jsonwebtoken::encode(&header, &claims, &self.key)
+    }
+}
+
+fn parse_claims(claims: &str) -> std::result::Result<Map<String, Value>, String> {
+    match serde_json::from_str(claims) {
+        Ok(Value::Object(claims)) => Ok(claims),
+        Ok(_) => Err("claims must be a JSON object".to_string()),
+        Err(e) => Err(format!("invalid claims: {}", e)),
+    }
+}
+
+fn oauth_error(error: &str, description: &str) -> Response {
+    (StatusCode::BAD_REQUEST, Json(json!({ "error": error, "error_description": description })))
+        .into_response()
+}
+
+/// Client ID from HTTP Basic authentication (`client_secret_basic`).
+fn basic_client_id(headers: &HeaderMap) -> Option<String> {
+    let credentials = headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Basic ")
+        .and_then(|encoded| STANDARD.decode(encoded.trim()).ok())?;
+    let credentials = String::from_utf8(credentials).ok()?;
+    let (client_id, _) = credentials.split_once(':')?;
+    Some(client_id.to_string())
+}
+
+pub async fn discovery(State(state): State<AppState>) -> Response {
+    let Some(oidc) = &state.oidc else { return StatusCode::NOT_FOUND.into_response() };
+
+    Json(json!({
+        "issuer": oidc.issuer,
+        "authorization_endpoint": format!("{}/authorize", oidc.issuer),
+        "jwks_uri": format!("{}/jwks.json", oidc.issuer),
+        "token_endpoint": format!("{}/token", oidc.issuer),
+        "grant_types_supported": ["client_credentials", "password"],
+        "response_types_supported": ["token"],
+        "subject_types_supported": ["public"],
+        "id_token_signing_alg_values_supported": [oidc.algorithm],
+        "token_endpoint_auth_methods_supported": ["client_secret_basic", "client_secret_post"],
+    }))
+    .into_response()
+}
+
+pub async fn jwks(State(state): State<AppState>) -> Response {
+    match &state.oidc {
+        Some(oidc) => Json(oidc.jwks()).into_response(),
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+/// Stub authorization endpoint, listed because discovery requires one. Tokens are only issued
+/// by `/token`, so every authorization request is refused.
+pub async fn authorize() -> Response {
+    oauth_error("unsupported_response_type", "tokens are only issued by the token endpoint")
+}
+
+/// Mints an access token for the `client_credentials` grant, plus an ID token for the
+/// `password` grant.
+pub async fn token(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    request: std::result::Result<Form<TokenRequest>, FormRejection>,
+) -> Response {
+    let Some(oidc) = &state.oidc else { return StatusCode::NOT_FOUND.into_response() };
+    let Form(request) = match request {
+        Ok(request) => request,
+        Err(e) => return oauth_error("invalid_request", &e.body_text()),
+    };
+
+    let client_id = request.client_id.clone().or_else(|| basic_client_id(&headers));
+    let subject = match (request.grant_type.as_str(), &request.username, &client_id) {
+        ("client_credentials", _, Some(client_id)) => client_id.clone(),
+        ("client_credentials", _, None) => {
+            return oauth_error("invalid_client", "client_id is required");
+        },
+        ("password", Some(username), _) => username.clone(),
+        ("password", None, _) => return oauth_error("invalid_request", "username is required"),
+        (grant_type, _, _) => {
+            return oauth_error(
+                "unsupported_grant_type",
+                &format!("grant type '{}' is not supported", grant_type),
+            );
+        },
+    };
+
+    let mut extra = match request.claims.as_deref().map(parse_claims).transpose() {
+        Ok(claims) => claims.unwrap_or_default(),
+        Err(e) => return oauth_error("invalid_request", &e),
+    };
+    if let Some(scope) = &request.scope {
+        extra.entry("scope").or_insert_with(|| json!(scope));
+    }
+    if let Some(client_id) = &client_id {
+        extra.entry("client_id").or_insert_with(|| json!(client_id));
+    }
+
+    let audience = request
+        .audience
+        .clone()
+        .or_else(|| oidc.audience.clone())
+        .or_else(|| client_id.clone())
+        .unwrap_or_else(|| oidc.issuer.clone());
+
+    let access_token = match oidc.issue(&subject, &audience, &extra) {
+        Ok(token) => token,
+        Err(e) => {
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "error": e.to_string() })))
+                .into_response();
+        },
+    };
+
+    let mut response = json!({
+        "access_token": access_token,
+        "token_type": "Bearer",
+        "expires_in": oidc.ttl_secs,
+    });
+    if let Some(scope) = &request.scope {
+        response["scope"] = json!(scope);
+    }
+    if request.grant_type == "password" {
+        let id_audience = client_id.as_deref().unwrap_or(&audience);
+        if let Ok(id_token) = oidc.issue(&subject, id_audience, &extra) {
+            response["id_token"] = json!(id_token);
+        }
+    }
+
+    Json(response).into_response()
+}
@@ -0,0 +1,207 @@
+use axum::{
+    body::Body,
+    http::{HeaderValue, request::Parts},
+    response::Response,
+};
+use bytes::{Bytes, BytesMut};
+use futures_util::{StreamExt, stream};
+use rand::{Rng, RngCore, SeedableRng, rngs::StdRng};
+use serde_json::{Map, Value};
+use std::str::FromStr;
+
+use crate::{connection::SkipCompression, handlers::request_control};
+
+/// Size of each chunk of a generated body.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Longest accepted text pattern.
+const MAX_PATTERN_LEN: usize = 1024;
+
+/// Upper bounds on the shape of a generated JSON document.
+const MAX_JSON_DEPTH: u32 = 64;
+const MAX_JSON_NODES: u64 = 1_000_000;
+
+/// Parses a byte size such as `512`, `64KB`, `10MB` or `1GiB`. Units are powers of 1024.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+
+    let number: u64 = number.parse().map_err(|_| format!("Invalid size: {}", s))?;
+    let multiplier: u64 = match unit.trim().to_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1 << 10,
+        "m" | "mb" | "mib" => 1 << 20,
+        "g" | "gb" | "gib" => 1 << 30,
+        _ => return Err(format!("Invalid size unit: {}", s)),
+    };
+
+    number.checked_mul(multiplier).ok_or_else(|| format!("Size too large: {}", s))
+}
+
+/// Byte pattern for generated bodies, from `x-set-response-pattern`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pattern {
+    /// Random bytes (default)
+    Random,
+    /// Zero bytes
+    Zeros,
+    /// The given text, repeated
+    Repeat(Bytes),
+}
+
+impl FromStr for Pattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "" => Err("Invalid pattern: empty".to_string()),
+            text if text.len() > MAX_PATTERN_LEN => {
+                Err(format!("Invalid pattern: longer than {} bytes", MAX_PATTERN_LEN))
+            },
+            "random" => Ok(Pattern::Random),
+            "zeros" => Ok(Pattern::Zeros),
+            text => Ok(Pattern::Repeat(Bytes::copy_from_slice(text.as_bytes()))),
+        }
+    }
+}
+
+/// A synthetic response body requested by the client.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Payload {
+    /// `x-set-response-size` bytes of the given pattern
+    Bytes { size: u64, pattern: Pattern },
+    /// Random JSON document from `x-set-response-json-depth` / `x-set-response-json-breadth`
+    Json { depth: u32, breadth: u32 },
+}
+
+impl Payload {
+    /// Reads payload controls from the request, if any are present.
+    pub fn from_request(parts: &Parts) -> Result<Option<Self>, String> {
+        if let Some(size) = request_control::<String>(parts, "x-set-response-size") {
+            let size = parse_size(&size)?;
+            let pattern = match request_control::<String>(parts, "x-set-response-pattern") {
+                Some(pattern) => pattern.parse()?,
+                None => Pattern::Random,
+            };
+            return Ok(Some(Payload::Bytes { size, pattern }));
+        }
+
+        let depth = request_control::<u32>(parts, "x-set-response-json-depth");
+        let breadth = request_control::<u32>(parts, "x-set-response-json-breadth");
+        if depth.is_none() && breadth.is_none() {
+            return Ok(None);
+        }
+
+        let depth = depth.unwrap_or(3);
+        let breadth = breadth.unwrap_or(3);
+        let nodes = (0..=depth).try_fold(0u64, |total, level| {
+            (breadth as u64).checked_pow(level).and_then(|n| total.checked_add(n))
+        });
+        if depth > MAX_JSON_DEPTH || nodes.is_none_or(|nodes| nodes > MAX_JSON_NODES) {
+            return Err(format!("JSON payload too large: depth {} breadth {}", depth, breadth));
+        }
+
+        Ok(Some(Payload::Json { depth, breadth }))
+    }
+
+    /// Builds the response. Byte payloads are generated chunk by chunk while streaming and
+    /// carry an exact `content-length`. Payloads are never compressed, so clients receive
+    /// exactly the requested bytes.
+    pub fn into_response(self, seed: u64) -> Response {
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        let mut response = match self {
+            Payload::Bytes { size, pattern } => {
+                let mut response = Response::new(generate_body(size, pattern, rng));
+                let headers = response.headers_mut();
+                headers
+                    .insert("content-type", HeaderValue::from_static("application/octet-stream"));
+                headers.insert("content-length", HeaderValue::from(size));
+                response
+            },
+            Payload::Json { depth, breadth } => {
+                let value = random_json(depth, breadth, &mut rng);
+                let mut response = Response::new(Body::from(value.to_string()));
+                response
+                    .headers_mut()
+                    .insert("content-type", HeaderValue::from_static("application/json"));
+                response
+            },
+        };
+
+        response.extensions_mut().insert(SkipCompression);
+        response
+    }
+}
+
+/// Streams exactly `size` bytes of `pattern` without buffering the whole body.
+pub fn generate_body(size: u64, pattern: Pattern, rng: StdRng) -> Body {
+    let block = match &pattern {
+        Pattern::Random => Bytes::new(),
+        Pattern::Zeros => Bytes::from(vec![0u8; CHUNK_SIZE]),
+        Pattern::Repeat(text) => {
+            let mut block = BytesMut::with_capacity(CHUNK_SIZE + text.len());
+            while block.len() < CHUNK_SIZE {
+                block.extend_from_slice(text);
+            }
+            block.freeze()
+        },
+    };
+
+    // Keep the pattern continuous across chunk boundaries
+    let period = match &pattern {
+        Pattern::Repeat(text) => text.len(),
+        _ => 1,
+    };
+    let step = CHUNK_SIZE - CHUNK_SIZE % period;
+    let random = pattern == Pattern::Random;
+
+    Body::from_stream(
+        stream::unfold((size, rng), move |(remaining, mut rng)| {
+            let block = block.clone();
+            async move {
+                if remaining == 0 {
+                    return None;
+                }
+
+                let len = remaining.min(step as u64) as usize;
+                let chunk = if random {
+                    let mut buffer = vec![0u8; len];
+                    rng.fill_bytes(&mut buffer);
+                    Bytes::from(buffer)
+                } else {
+                    block.slice(..len)
+                };
+
+                Some((Ok::<Bytes, std::io::Error>(chunk), (remaining - len as u64, rng)))
+            }
+        })
+        .fuse(),
+    )
+}
+
+/// Generates a JSON object nested `depth` levels deep with `breadth` keys per object.
+pub fn random_json(depth: u32, breadth: u32, rng: &mut impl Rng) -> Value {
+    if depth == 0 {
+        return random_leaf(rng);
+    }
+
+    let map: Map<String, Value> =
+        (0..breadth).map(|i| (format!("key{}", i), random_json(depth - 1, breadth, rng))).collect();
+
+    Value::Object(map)
+}
+
+fn random_leaf(rng: &mut impl Rng) -> Value {
+    match rng.random_range(0..4) {
+        0 => Value::from(rng.random_range(0..1_000_000)),
+        1 => Value::from(rng.random::<f64>()),
+        2 => Value::from(rng.random::<bool>()),
+        _ => {
+            let text: String =
+                (0..12).map(|_| rng.sample(rand::distr::Alphanumeric) as char).collect();
+            Value::from(text)
+        },
+    }
+}
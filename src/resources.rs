@@ -0,0 +1,193 @@
+use axum::Json;
+use serde::Serialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Values at or above this in cgroup v1 limit files mean "unlimited".
+const V1_UNLIMITED: u64 = 1 << 62;
+
+/// Resource limits and usage of the container, as seen by the process.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ResourceInfo {
+    /// 1 or 2, absent when no cgroup hierarchy was found
+    pub cgroup_version: Option<u8>,
+    /// CPUs the process may use, accounting for affinity and CPU quota
+    pub cpu_count: usize,
+    pub cpu: CpuInfo,
+    pub memory: MemoryInfo,
+    pub pids: PidsInfo,
+    pub process: ProcessInfo,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CpuInfo {
+    /// CPU limit in cores (quota / period), absent when unlimited
+    pub limit_cores: Option<f64>,
+    pub quota_us: Option<u64>,
+    pub period_us: Option<u64>,
+    /// `cpu.weight` (v2) or `cpu.shares` (v1)
+    pub weight: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MemoryInfo {
+    /// Absent when unlimited
+    pub limit_bytes: Option<u64>,
+    pub usage_bytes: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PidsInfo {
+    /// Absent when unlimited
+    pub limit: Option<u64>,
+    pub current: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ProcessInfo {
+    pub rss_bytes: Option<u64>,
+    pub threads: Option<u64>,
+}
+
+impl ResourceInfo {
+    /// Reads the live values from `/sys/fs/cgroup` and `/proc`.
+    pub fn current() -> Self {
+        Self::read(Path::new("/sys/fs/cgroup"), Path::new("/proc"))
+    }
+
+    /// Reads from a cgroup mount and a procfs mount at the given paths.
+    pub fn read(cgroup_root: &Path, proc_root: &Path) -> Self {
+        let memberships = fs::read_to_string(proc_root.join("self/cgroup")).unwrap_or_default();
+        let status = fs::read_to_string(proc_root.join("self/status")).unwrap_or_default();
+        let cpu_count = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1);
+
+        let process = ProcessInfo {
+            rss_bytes: status_field(&status, "VmRSS:").map(|kb| kb * 1024),
+            threads: status_field(&status, "Threads:"),
+        };
+
+        let mut info = if cgroup_root.join("cgroup.controllers").exists() {
+            let dir = v2_dir(cgroup_root, &memberships);
+            read_v2(&dir)
+        } else if cgroup_root.join("memory").exists() || cgroup_root.join("cpu").exists() {
+            read_v1(cgroup_root, &memberships)
+        } else {
+            ResourceInfo::default()
+        };
+
+        info.cpu_count = cpu_count;
+        info.process = process;
+        info
+    }
+}
+
+fn read_v2(dir: &Path) -> ResourceInfo {
+    let (quota_us, period_us) = match read_string(&dir.join("cpu.max")) {
+        Some(max) => {
+            let mut fields = max.split_whitespace();
+            let quota = fields.next().and_then(|q| q.parse().ok());
+            (quota, fields.next().and_then(|p| p.parse().ok()))
+        },
+        None => (None, None),
+    };
+
+    ResourceInfo {
+        cgroup_version: Some(2),
+        cpu: cpu_info(quota_us, period_us, read_u64(&dir.join("cpu.weight"))),
+        memory: MemoryInfo {
+            limit_bytes: read_u64(&dir.join("memory.max")),
+            usage_bytes: read_u64(&dir.join("memory.current")),
+        },
+        pids: PidsInfo {
+            limit: read_u64(&dir.join("pids.max")),
+            current: read_u64(&dir.join("pids.current")),
+        },
+        ..Default::default()
+    }
+}
+
+fn read_v1(root: &Path, memberships: &str) -> ResourceInfo {
+    let cpu = v1_dir(root, memberships, "cpu");
+    let memory = v1_dir(root, memberships, "memory");
+    let pids = v1_dir(root, memberships, "pids");
+
+    // A quota of -1 means unlimited and fails to parse as u64
+    let quota_us = read_u64(&cpu.join("cpu.cfs_quota_us"));
+    let period_us = read_u64(&cpu.join("cpu.cfs_period_us"));
+
+    ResourceInfo {
+        cgroup_version: Some(1),
+        cpu: cpu_info(quota_us, period_us, read_u64(&cpu.join("cpu.shares"))),
+        memory: MemoryInfo {
+            limit_bytes: read_u64(&memory.join("memory.limit_in_bytes"))
+                .filter(|&limit| limit < V1_UNLIMITED),
+            usage_bytes: read_u64(&memory.join("memory.usage_in_bytes")),
+        },
+        pids: PidsInfo {
+            limit: read_u64(&pids.join("pids.max")),
+            current: read_u64(&pids.join("pids.current")),
+        },
+        ..Default::default()
+    }
+}
+
+fn cpu_info(quota_us: Option<u64>, period_us: Option<u64>, weight: Option<u64>) -> CpuInfo {
+    let limit_cores = match (quota_us, period_us) {
+        (Some(quota), Some(period)) if period > 0 => Some(quota as f64 / period as f64),
+        _ => None,
+    };
+
+    CpuInfo { limit_cores, quota_us, period_us, weight }
+}
+
+/// The process's own cgroup v2 directory (`0::/path` in `/proc/self/cgroup`), or the root
+/// when the path is not visible, e.g. inside a cgroup namespace.
+fn v2_dir(root: &Path, memberships: &str) -> PathBuf {
+    memberships
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))
+        .map(|path| root.join(path.trim_start_matches('/')))
+        .filter(|dir| dir.join("cgroup.controllers").exists() || dir.join("cpu.max").exists())
+        .unwrap_or_else(|| root.to_path_buf())
+}
+
+/// The process's cgroup v1 directory for `controller`, matching lines such as
+/// `4:cpu,cpuacct:/path`, falling back to the controller's mount root.
+fn v1_dir(root: &Path, memberships: &str, controller: &str) -> PathBuf {
+    let mount = root.join(controller);
+
+    memberships
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, ':');
+            fields.next()?;
+            Some((fields.next()?, fields.next()?))
+        })
+        .find(|(controllers, _)| controllers.split(',').any(|c| c == controller))
+        .map(|(_, path)| mount.join(path.trim_start_matches('/')))
+        .filter(|dir| dir.exists())
+        .unwrap_or(mount)
+}
+
+fn read_string(path: &Path) -> Option<String> {
+    fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+}
+
+/// Parses a single-number file; `max` and other non-numbers yield `None`.
+fn read_u64(path: &Path) -> Option<u64> {
+    read_string(path).and_then(|s| s.parse().ok())
+}
+
+fn status_field(status: &str, name: &str) -> Option<u64> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix(name))
+        .and_then(|value| value.split_whitespace().next())
+        .and_then(|value| value.parse().ok())
+}
+
+pub async fn resources_handler() -> Json<ResourceInfo> {
+    Json(ResourceInfo::current())
+}
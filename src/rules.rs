@@ -0,0 +1,317 @@
+use axum::{
+    body::Body,
+    http::{HeaderMap, HeaderName, HeaderValue, Method, StatusCode, request::Parts},
+    response::Response,
+};
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::time::sleep;
+
+use crate::{
+    error::{AppError, Result},
+    template,
+};
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl OneOrMany {
+    pub(crate) fn into_vec(self) -> Vec<String> {
+        match self {
+            OneOrMany::One(value) => vec![value],
+            OneOrMany::Many(values) => values,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RulesFile {
+    #[serde(default)]
+    rules: Vec<RuleSpec>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RuleSpec {
+    name: Option<String>,
+    #[serde(rename = "match", default)]
+    matcher: MatchSpec,
+    #[serde(default)]
+    response: ResponseSpec,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct MatchSpec {
+    method: Option<OneOrMany>,
+    path: Option<String>,
+    path_regex: Option<String>,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    #[serde(default)]
+    query: HashMap<String, String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ResponseSpec {
+    status: Option<u16>,
+    #[serde(default)]
+    headers: HashMap<String, OneOrMany>,
+    body: Option<String>,
+    body_file: Option<PathBuf>,
+    delay_ms: Option<u64>,
+    #[serde(default)]
+    template: bool,
+}
+
+/// Response extension naming the mock rule that produced a response.
+#[derive(Debug, Clone)]
+pub struct MatchedRule(pub String);
+
+/// A single mock rule: request matchers plus the canned response to send back.
+#[derive(Debug)]
+pub struct Rule {
+    pub name: Option<String>,
+    methods: Vec<Method>,
+    path: Vec<Regex>,
+    headers: Vec<(HeaderName, String)>,
+    query: Vec<(String, String)>,
+    pub response: RuleResponse,
+}
+
+#[derive(Debug)]
+pub struct RuleResponse {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Option<String>,
+    pub body_file: Option<PathBuf>,
+    pub delay: Option<Duration>,
+    /// Render the body as a template against the echoed request.
+    pub template: bool,
+}
+
+/// Ordered set of rules loaded from `--rules-file`. The first matching rule wins.
+#[derive(Debug, Default)]
+pub struct RuleSet {
+    rules: Vec<Rule>,
+}
+
+impl RuleSet {
+    /// Loads rules from a YAML or TOML file, chosen by extension (`.toml` is TOML, anything
+    /// else is parsed as YAML). Relative `body_file` paths resolve against the rules file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| AppError::Rules(format!("failed to read {}: {}", path.display(), e)))?;
+        let is_toml = path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("toml"));
+
+        let file: RulesFile = if is_toml {
+            toml::from_str(&contents).map_err(|e| AppError::Rules(e.to_string()))?
+        } else {
+            serde_yaml::from_str(&contents).map_err(|e| AppError::Rules(e.to_string()))?
+        };
+
+        let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let rules = file
+            .rules
+            .into_iter()
+            .enumerate()
+            .map(|(index, spec)| Rule::compile(spec, base_dir).map_err(|e| rule_error(index, e)))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(RuleSet { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    /// Returns the first rule matching the request, if any.
+    pub fn find(&self, parts: &Parts) -> Option<&Rule> {
+        self.rules.iter().find(|rule| rule.matches(parts))
+    }
+}
+
+fn rule_error(index: usize, error: AppError) -> AppError {
+    AppError::Rules(format!("rule #{}: {}", index + 1, error))
+}
+
+impl Rule {
+    fn compile(spec: RuleSpec, base_dir: &Path) -> Result<Self> {
+        let matcher = spec.matcher;
+
+        let methods = matcher
+            .method
+            .map(OneOrMany::into_vec)
+            .unwrap_or_default()
+            .iter()
+            .map(|m| {
+                Method::from_bytes(m.to_uppercase().as_bytes())
+                    .map_err(|_| AppError::Rules(format!("invalid method: {}", m)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut path = Vec::new();
+        if let Some(glob) = &matcher.path {
+            path.push(compile_regex(&glob_to_regex(glob))?);
+        }
+        if let Some(pattern) = &matcher.path_regex {
+            path.push(compile_regex(pattern)?);
+        }
+
+        let headers = matcher
+            .headers
+            .into_iter()
+            .map(|(name, value)| {
+                HeaderName::from_bytes(name.as_bytes())
+                    .map(|name| (name, value))
+                    .map_err(|_| AppError::Rules(format!("invalid header name: {}", name)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let response = spec.response;
+        let status = StatusCode::from_u16(response.status.unwrap_or(200))
+            .map_err(|_| AppError::Rules(format!("invalid status: {:?}", response.status)))?;
+
+        let mut response_headers = HeaderMap::new();
+        for (name, values) in response.headers {
+            let header_name = HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| AppError::Rules(format!("invalid header name: {}", name)))?;
+            for value in values.into_vec() {
+                let header_value = HeaderValue::from_str(&value)
+                    .map_err(|_| AppError::Rules(format!("invalid value for {}", name)))?;
+                response_headers.append(header_name.clone(), header_value);
+            }
+        }
+
+        Ok(Rule {
+            name: spec.name,
+            methods,
+            path,
+            headers,
+            query: matcher.query.into_iter().collect(),
+            response: RuleResponse {
+                status,
+                headers: response_headers,
+                body: response.body,
+                body_file: response.body_file.map(|file| base_dir.join(file)),
+                delay: response.delay_ms.map(Duration::from_millis),
+                template: response.template,
+            },
+        })
+    }
+
+    fn matches(&self, parts: &Parts) -> bool {
+        if !self.methods.is_empty() && !self.methods.contains(&parts.method) {
+            return false;
+        }
+
+        if !self.path.iter().all(|re| re.is_match(parts.uri.path())) {
+            return false;
+        }
+
+        let headers_match = self.headers.iter().all(|(name, expected)| {
+            parts.headers.get_all(name).iter().any(|v| v.to_str().ok() == Some(expected))
+        });
+        if !headers_match {
+            return false;
+        }
+
+        if self.query.is_empty() {
+            return true;
+        }
+
+        let query: Vec<(String, String)> = parts
+            .uri
+            .query()
+            .map(|q| url::form_urlencoded::parse(q.as_bytes()).into_owned().collect())
+            .unwrap_or_default();
+
+        self.query
+            .iter()
+            .all(|(key, expected)| query.iter().any(|(k, v)| k == key && v == expected))
+    }
+
+    /// Builds the configured response, honouring the rule's delay. When `context` is given
+    /// the body is rendered as a template against it.
+    pub async fn respond(&self, context: Option<&Value>) -> Response {
+        let rule = &self.response;
+
+        if let Some(delay) = rule.delay {
+            sleep(delay).await;
+        }
+
+        let (body, default_content_type) = if let Some(file_path) = &rule.body_file {
+            match tokio::fs::read(file_path).await {
+                Ok(contents) => (
+                    render_body(contents, context),
+                    mime_guess::from_path(file_path).first_or_octet_stream().to_string(),
+                ),
+                Err(e) => {
+                    return Response::builder()
+                        .status(StatusCode::INTERNAL_SERVER_ERROR)
+                        .body(Body::from(format!("Failed to read file: {}", e)))
+                        .unwrap();
+                },
+            }
+        } else {
+            (
+                render_body(rule.body.clone().unwrap_or_default().into_bytes(), context),
+                "text/plain; charset=utf-8".to_string(),
+            )
+        };
+
+        let mut response = Response::new(body);
+        *response.status_mut() = rule.status;
+        if let Ok(header_value) = HeaderValue::from_str(&default_content_type) {
+            response.headers_mut().insert("content-type", header_value);
+        }
+        response.headers_mut().extend(rule.headers.clone());
+
+        response
+    }
+}
+
+fn render_body(contents: Vec<u8>, context: Option<&Value>) -> Body {
+    match context {
+        Some(context) => Body::from(template::render(&String::from_utf8_lossy(&contents), context)),
+        None => Body::from(contents),
+    }
+}
+
+fn compile_regex(pattern: &str) -> Result<Regex> {
+    Regex::new(pattern).map_err(|e| AppError::Rules(e.to_string()))
+}
+
+/// Converts a path glob into an anchored regex. `*` matches within a single path segment,
+/// `**` matches across segments and `?` matches one character.
+fn glob_to_regex(glob: &str) -> String {
+    let mut pattern = String::from("^");
+    let mut chars = glob.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                pattern.push_str(".*");
+            },
+            '*' => pattern.push_str("[^/]*"),
+            '?' => pattern.push_str("[^/]"),
+            _ => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+
+    pattern.push('$');
+    pattern
+}
@@ -0,0 +1,134 @@
+use axum::{
+    body::{Body, BodyDataStream},
+    extract::Request,
+    middleware::Next,
+    response::Response,
+};
+use bytes::{Bytes, BytesMut};
+use futures_util::{StreamExt, stream};
+use std::time::Duration;
+use tokio::time::sleep;
+
+use crate::{connection::SkipCompression, handlers::request_control};
+
+/// Chunk size used when only an interval is given.
+const DEFAULT_CHUNK_SIZE: usize = 1024;
+
+/// Pacing for a streamed response body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StreamSettings {
+    /// Maximum bytes per chunk
+    pub chunk_size: usize,
+    /// Minimum pause between chunks
+    pub interval: Option<Duration>,
+    /// Bandwidth cap in bytes per second
+    pub bytes_per_second: Option<u64>,
+}
+
+impl StreamSettings {
+    /// Reads streaming controls from the request. Returns `None` unless at least one of
+    /// `x-set-response-chunk-size`, `x-set-response-chunk-interval-ms` or
+    /// `x-set-response-bytes-per-second` is present.
+    pub fn from_request(parts: &axum::http::request::Parts) -> Option<Self> {
+        let chunk_size = request_control::<usize>(parts, "x-set-response-chunk-size");
+        let interval = request_control::<u64>(parts, "x-set-response-chunk-interval-ms")
+            .map(Duration::from_millis);
+        let bytes_per_second =
+            request_control::<u64>(parts, "x-set-response-bytes-per-second").filter(|&bps| bps > 0);
+
+        if chunk_size.is_none() && interval.is_none() && bytes_per_second.is_none() {
+            return None;
+        }
+
+        // Default to roughly ten chunks per second under a bandwidth cap
+        let default_chunk_size =
+            bytes_per_second.map(|bps| (bps / 10).max(1) as usize).unwrap_or(DEFAULT_CHUNK_SIZE);
+
+        Some(StreamSettings {
+            chunk_size: chunk_size.unwrap_or(default_chunk_size).max(1),
+            interval,
+            bytes_per_second,
+        })
+    }
+
+    /// Pause before sending the next chunk, given the size of the previous one.
+    fn pause_after(&self, sent: usize) -> Duration {
+        let interval = self.interval.unwrap_or_default();
+        let bandwidth = self
+            .bytes_per_second
+            .map(|bps| Duration::from_secs_f64(sent as f64 / bps as f64))
+            .unwrap_or_default();
+
+        interval.max(bandwidth)
+    }
+}
+
+/// Middleware that streams the response body in paced chunks when streaming controls are
+/// present. The body is sent with chunked transfer encoding on HTTP/1.1 and as separate
+/// DATA frames on HTTP/2, uncompressed so the chunks reach the client as paced.
+pub async fn stream_response(request: Request, next: Next) -> Response {
+    let (parts, body) = request.into_parts();
+    let settings = StreamSettings::from_request(&parts);
+    let response = next.run(Request::from_parts(parts, body)).await;
+
+    let Some(settings) = settings else {
+        return response;
+    };
+
+    let (mut parts, body) = response.into_parts();
+    parts.headers.remove("content-length");
+    parts.extensions.insert(SkipCompression);
+
+    Response::from_parts(parts, throttled_body(body, settings))
+}
+
+struct Throttle {
+    inner: BodyDataStream,
+    buffer: BytesMut,
+    settings: StreamSettings,
+    last_sent: Option<usize>,
+    finished: bool,
+}
+
+/// Re-chunks `body` into pieces of at most `settings.chunk_size` bytes, pausing between them.
+/// The inner body is pulled lazily, so large generated bodies are never fully buffered.
+pub fn throttled_body(body: Body, settings: StreamSettings) -> Body {
+    let state = Throttle {
+        inner: body.into_data_stream(),
+        buffer: BytesMut::new(),
+        settings,
+        last_sent: None,
+        finished: false,
+    };
+
+    Body::from_stream(
+        stream::unfold(state, |mut state| async move {
+            while state.buffer.len() < state.settings.chunk_size && !state.finished {
+                match state.inner.next().await {
+                    Some(Ok(bytes)) => state.buffer.extend_from_slice(&bytes),
+                    Some(Err(e)) => {
+                        state.finished = true;
+                        state.buffer.clear();
+                        return Some((Err(e), state));
+                    },
+                    None => state.finished = true,
+                }
+            }
+
+            if state.buffer.is_empty() {
+                return None;
+            }
+
+            if let Some(sent) = state.last_sent {
+                sleep(state.settings.pause_after(sent)).await;
+            }
+
+            let len = state.settings.chunk_size.min(state.buffer.len());
+            let chunk: Bytes = state.buffer.split_to(len).freeze();
+            state.last_sent = Some(chunk.len());
+
+            Some((Ok(chunk), state))
+        })
+        .fuse(),
+    )
+}
@@ -0,0 +1,109 @@
+use anyhow::{Context, Result, bail};
+use serde_json::Value;
+
+use k8swalski::config::TailArgs;
+
+/// Connects to a running server's `/__stream` endpoint and prints each request it reports.
+pub async fn run(args: &TailArgs) -> Result<()> {
+    let mut url = url::Url::parse(&args.url)
+        .and_then(|base| base.join("/__stream"))
+        .with_context(|| format!("Invalid server URL: {}", args.url))?;
+
+    {
+        let mut query = url.query_pairs_mut();
+        for (key, value) in
+            [("path", &args.path), ("path_prefix", &args.path_prefix), ("method", &args.method)]
+        {
+            if let Some(value) = value {
+                query.append_pair(key, value);
+            }
+        }
+    }
+
+    let mut response = reqwest::Client::new()
+        .get(url.clone())
+        .header("accept", "text/event-stream")
+        .send()
+        .await
+        .with_context(|| format!("Failed to connect to {}", url))?;
+
+    if !response.status().is_success() {
+        bail!("{} returned {}", url, response.status());
+    }
+
+    // Without --enable-stream the request falls through to the echo handler
+    let is_event_stream = response
+        .headers()
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/event-stream"));
+    if !is_event_stream {
+        bail!("{} is not a live tail; start the server with --enable-stream", url);
+    }
+
+    eprintln!("Watching {} (Ctrl+C to stop)", args.url);
+
+    let mut buffer = String::new();
+    while let Some(chunk) = response.chunk().await.context("Stream interrupted")? {
+        buffer.push_str(&String::from_utf8_lossy(&chunk).replace("\r\n", "\n"));
+
+        // Events are separated by a blank line
+        while let Some(end) = buffer.find("\n\n") {
+            let event: String = buffer.drain(..end + 2).collect();
+            let data: Vec<&str> = event
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(str::trim_start)
+                .collect();
+
+            if data.is_empty() {
+                continue;
+            }
+
+            let data = data.join("\n");
+            if args.json {
+                println!("{}", data);
+            } else if let Ok(entry) = serde_json::from_str::<Value>(&data) {
+                print_entry(&entry, args.verbose);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn print_entry(entry: &Value, verbose: bool) {
+    let request = &entry["request"];
+    let text = |value: &Value| value.as_str().unwrap_or("-").to_string();
+
+    println!(
+        "{}  {:<7} {}  {}  {}ms  {}",
+        text(&entry["timestamp"]),
+        text(&request["method"]),
+        text(&request["path"]),
+        entry["status"],
+        entry["duration_ms"],
+        text(&request["ip"]),
+    );
+
+    if !verbose {
+        return;
+    }
+
+    if let Some(headers) = request["headers"].as_object() {
+        let mut headers: Vec<_> = headers.iter().collect();
+        headers.sort_by_key(|(name, _)| name.as_str());
+        for (name, value) in headers {
+            println!("    {}: {}", name, text(value));
+        }
+    }
+
+    if let Some(body) = request["body"].as_str().filter(|body| !body.is_empty()) {
+        println!();
+        for line in body.lines() {
+            println!("    {}", line);
+        }
+    }
+
+    println!();
+}
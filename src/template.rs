@@ -0,0 +1,110 @@
+use rand::Rng;
+use serde_json::Value;
+use std::fmt::Write;
+
+/// Renders `{{ ... }}` placeholders in `template` against the echoed request.
+///
+/// A placeholder is either a dotted path into the echo response (`{{path}}`,
+/// `{{headers.x-user}}`, `{{query.id}}`, `{{json.order.id}}`, `{{json.items.0}}`) or a
+/// helper call:
+///
+/// - `{{now}}` / `{{now "%Y-%m-%d"}}` - current UTC time, RFC 3339 or strftime format (an
+///   invalid format renders as an empty string)
+/// - `{{timestamp}}` - current Unix time in seconds
+/// - `{{uuid}}` - random v4 UUID
+/// - `{{random_int}}` / `{{random_int 1 6}}` - random integer, inclusive (default 0-100)
+///
+/// Strings render verbatim, other JSON values render as JSON, and unknown paths render
+/// as an empty string.
+pub fn render(template: &str, context: &Value) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+
+        match after.find("}}") {
+            Some(end) => {
+                output.push_str(&evaluate(after[..end].trim(), context));
+                rest = &after[end + 2..];
+            },
+            None => {
+                output.push_str(&rest[start..]);
+                rest = "";
+            },
+        }
+    }
+
+    output.push_str(rest);
+    output
+}
+
+fn evaluate(expression: &str, context: &Value) -> String {
+    let args = tokenize(expression);
+    let Some((name, args)) = args.split_first() else {
+        return String::new();
+    };
+
+    match name.as_str() {
+        "now" => match args.first() {
+            Some(format) => {
+                // Invalid strftime formats fail while formatting rather than when parsed
+                let mut output = String::new();
+                match write!(output, "{}", chrono::Utc::now().format(format)) {
+                    Ok(()) => output,
+                    Err(_) => String::new(),
+                }
+            },
+            None => chrono::Utc::now().to_rfc3339(),
+        },
+        "timestamp" => chrono::Utc::now().timestamp().to_string(),
+        "uuid" => uuid::Uuid::new_v4().to_string(),
+        "random_int" => {
+            let min = args.first().and_then(|v| v.parse().ok()).unwrap_or(0i64);
+            let max = args.get(1).and_then(|v| v.parse().ok()).unwrap_or(100i64);
+            if min > max { String::new() } else { rand::rng().random_range(min..=max).to_string() }
+        },
+        _ => lookup(context, name).map(value_to_string).unwrap_or_default(),
+    }
+}
+
+fn lookup<'a>(context: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(context, |value, key| match value {
+        Value::Object(map) => map.get(key),
+        Value::Array(items) => key.parse::<usize>().ok().and_then(|i| items.get(i)),
+        _ => None,
+    })
+}
+
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+fn tokenize(expression: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+
+    for c in expression.chars() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            c if c.is_whitespace() && !in_quotes => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            },
+            c => current.push(c),
+        }
+    }
+
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+
+    tokens
+}
@@ -0,0 +1,190 @@
+use axum::http::Request;
+use axum_server::accept::Accept;
+use futures_util::future::BoxFuture;
+use rustls::{
+    HandshakeKind, ProtocolVersion, ServerConfig, ServerConnection,
+    pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+    server::{WebPkiClientVerifier, danger::ClientCertVerifier},
+};
+use std::{
+    io,
+    path::Path,
+    sync::Arc,
+    task::{Context, Poll},
+};
+use tokio_rustls::server::TlsStream;
+use tower::Service;
+
+#[cfg(feature = "mtls")]
+use crate::config::ClientAuth;
+use crate::{
+    config::Config,
+    error::{AppError, Result},
+};
+
+/// Builds the HTTPS listener's TLS configuration from the PEM certificate chain and key,
+/// advertising HTTP/2 and HTTP/1.1 via ALPN.
+pub fn server_config(cert_path: &Path, key_path: &Path, config: &Config) -> Result<ServerConfig> {
+    let certs = read_certificates(cert_path)?;
+    let key = PrivateKeyDer::from_pem_file(key_path).map_err(|e| {
+        AppError::InvalidCertificate(format!("cannot read key {}: {}", key_path.display(), e))
+    })?;
+
+    let mut server_config = ServerConfig::builder()
+        .with_client_cert_verifier(client_verifier(config)?)
+        .with_single_cert(certs, key)
+        .map_err(|e| AppError::TlsConfig(e.to_string()))?;
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(server_config)
+}
+
+/// Verifies client certificates against `--tls-client-ca` according to `--tls-client-auth`.
+#[cfg(feature = "mtls")]
+fn client_verifier(config: &Config) -> Result<Arc<dyn ClientCertVerifier>> {
+    let mode = config.tls_client_auth.unwrap_or(match config.tls_client_ca {
+        Some(_) => ClientAuth::Required,
+        None => ClientAuth::None,
+    });
+    let ca_path = match (mode, &config.tls_client_ca) {
+        (ClientAuth::None, _) => return Ok(WebPkiClientVerifier::no_client_auth()),
+        (_, Some(path)) => path,
+        (_, None) => {
+            return Err(AppError::TlsConfig(format!(
+                "client auth mode '{}' requires --tls-client-ca",
+                mode
+            )));
+        },
+    };
+
+    let mut roots = rustls::RootCertStore::empty();
+    for cert in read_certificates(ca_path)? {
+        roots
+            .add(cert)
+            .map_err(|e| AppError::InvalidCertificate(format!("{}: {}", ca_path.display(), e)))?;
+    }
+
+    let builder = WebPkiClientVerifier::builder(Arc::new(roots));
+    let builder = match mode {
+        ClientAuth::Optional => builder.allow_unauthenticated(),
+        _ => builder,
+    };
+    builder.build().map_err(|e| AppError::TlsConfig(e.to_string()))
+}
+
+#[cfg(not(feature = "mtls"))]
+fn client_verifier(_config: &Config) -> Result<Arc<dyn ClientCertVerifier>> {
+    Ok(WebPkiClientVerifier::no_client_auth())
+}
+
+fn read_certificates(path: &Path) -> Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<std::result::Result<Vec<_>, _>>())
+        .map_err(|e| AppError::InvalidCertificate(format!("{}: {}", path.display(), e)))?;
+
+    if certs.is_empty() {
+        return Err(AppError::InvalidCertificate(format!(
+            "{}: no certificates found",
+            path.display()
+        )));
+    }
+    Ok(certs)
+}
+
+/// Parameters negotiated in the TLS handshake, inserted as a request extension by
+/// [`TlsInfoAcceptor`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TlsInfo {
+    /// `TLSv1.2` or `TLSv1.3`
+    pub version: Option<String>,
+    /// IANA name of the cipher suite, e.g. `TLS13_AES_256_GCM_SHA384`
+    pub cipher_suite: Option<String>,
+    pub alpn_protocol: Option<String>,
+    /// Server name sent by the client via SNI
+    pub servername: Option<String>,
+    pub resumed: Option<bool>,
+    /// Certificate chain presented by the client, leaf first
+    pub peer_certificates: Vec<CertificateDer<'static>>,
+}
+
+impl TlsInfo {
+    pub fn from_connection(connection: &ServerConnection) -> Self {
+        TlsInfo {
+            version: connection.protocol_version().map(|version| match version {
+                ProtocolVersion::TLSv1_2 => "TLSv1.2".to_string(),
+                ProtocolVersion::TLSv1_3 => "TLSv1.3".to_string(),
+                other => format!("{:?}", other),
+            }),
+            cipher_suite: connection
+                .negotiated_cipher_suite()
+                .map(|suite| format!("{:?}", suite.suite())),
+            alpn_protocol: connection
+                .alpn_protocol()
+                .map(|protocol| String::from_utf8_lossy(protocol).into_owned()),
+            servername: connection.server_name().map(|name| name.to_string()),
+            resumed: connection.handshake_kind().map(|kind| kind == HandshakeKind::Resumed),
+            peer_certificates: connection
+                .peer_certificates()
+                .map(<[_]>::to_vec)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Wraps the TLS acceptor and records the negotiated [`TlsInfo`] of every connection once its
+/// handshake has completed.
+#[derive(Debug, Clone)]
+pub struct TlsInfoAcceptor<A> {
+    inner: A,
+}
+
+impl<A> TlsInfoAcceptor<A> {
+    pub fn new(inner: A) -> Self {
+        TlsInfoAcceptor { inner }
+    }
+}
+
+impl<A, I, S, T> Accept<I, S> for TlsInfoAcceptor<A>
+where
+    A: Accept<I, S, Stream = TlsStream<T>>,
+    A::Future: Send + 'static,
+    A::Service: Send + 'static,
+    T: Send + 'static,
+{
+    type Stream = TlsStream<T>;
+    type Service = WithTlsInfo<A::Service>;
+    type Future = BoxFuture<'static, io::Result<(Self::Stream, Self::Service)>>;
+
+    fn accept(&self, stream: I, service: S) -> Self::Future {
+        let accept = self.inner.accept(stream, service);
+        Box::pin(async move {
+            let (stream, inner) = accept.await?;
+            let info = TlsInfo::from_connection(stream.get_ref().1);
+            Ok((stream, WithTlsInfo { inner, info }))
+        })
+    }
+}
+
+/// Per-connection service that exposes the [`TlsInfo`] to every request.
+#[derive(Debug, Clone)]
+pub struct WithTlsInfo<S> {
+    inner: S,
+    info: TlsInfo,
+}
+
+impl<S, B> Service<Request<B>> for WithTlsInfo<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<std::result::Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<B>) -> Self::Future {
+        request.extensions_mut().insert(self.info.clone());
+        self.inner.call(request)
+    }
+}